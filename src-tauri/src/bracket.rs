@@ -0,0 +1,323 @@
+use std::fmt;
+use std::fmt::Formatter;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ValueRef};
+use crate::channels::Channel;
+use crate::race_engine::RaceFormat;
+
+pub const PILOTS_PER_RACE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum BracketType {
+    SingleElimination,
+    DoubleElimination,
+}
+
+impl fmt::Display for BracketType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromSql for BracketType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().and_then(|s| {
+            match s {
+                "SingleElimination" => Ok(BracketType::SingleElimination),
+                "DoubleElimination" => Ok(BracketType::DoubleElimination),
+                _ => Err(FromSqlError::InvalidType)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum BracketSide {
+    Winners,
+    Losers,
+    Final,
+}
+
+impl fmt::Display for BracketSide {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromSql for BracketSide {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().and_then(|s| {
+            match s {
+                "Winners" => Ok(BracketSide::Winners),
+                "Losers" => Ok(BracketSide::Losers),
+                "Final" => Ok(BracketSide::Final),
+                _ => Err(FromSqlError::InvalidType)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum BracketSeeding {
+    Standings,
+    Consecutive { laps: u32 },
+    Manual { pilot_ids: Vec<i64> },
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct NewBracketDto {
+    pub race_event_id: i64,
+    pub name: String,
+    pub bracket_type: BracketType,
+    pub channels: Vec<Channel>,
+    pub seeding: BracketSeeding,
+    pub pilots: Option<usize>,
+    #[serde(default)]
+    pub format: RaceFormat,
+    pub round_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BracketRace {
+    pub race_id: i64,
+    pub side: BracketSide,
+    pub round: u32,
+    pub position: u32,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Bracket {
+    pub id: i64,
+    pub name: String,
+    pub bracket_type: BracketType,
+    pub channels: Vec<Channel>,
+    pub races: Vec<BracketRace>,
+}
+
+impl Bracket {
+    pub fn new(id: i64, name: String, bracket_type: BracketType, channels: Vec<Channel>, races: Vec<BracketRace>) -> Bracket {
+        Bracket { id, name, bracket_type, channels, races }
+    }
+}
+
+// A slot of a later race is filled by whoever finishes at `place` in the race planned at `source`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feed {
+    pub slot: u8,
+    pub source: usize,
+    pub place: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedRace {
+    pub side: BracketSide,
+    pub round: u32,
+    pub position: u32,
+    pub pilot_ids: Vec<i64>,
+    pub feeds: Vec<Feed>,
+}
+
+impl PlannedRace {
+    pub fn name(&self) -> String {
+        match self.side {
+            BracketSide::Final => "Final".to_string(),
+            side => format!("{} R{} Race {}", side, self.round, self.position),
+        }
+    }
+}
+
+struct Planner {
+    races: Vec<PlannedRace>,
+}
+
+impl Planner {
+    fn push(&mut self, side: BracketSide, round: u32, position: u32, pilot_ids: Vec<i64>, feeds: Vec<Feed>) -> usize {
+        self.races.push(PlannedRace { side, round, position, pilot_ids, feeds });
+        self.races.len() - 1
+    }
+
+    // Each pair of sources sends the pilots finishing at `places` into one race of the next round.
+    fn merge(&mut self, side: BracketSide, round: u32, sources: &[(usize, [u32; 2])]) -> Vec<usize> {
+        sources
+            .chunks(2)
+            .enumerate()
+            .map(|(index, pair)| {
+                let feeds = pair.iter()
+                    .flat_map(|(source, places)| places.iter().map(move |place| (*source, *place)))
+                    .enumerate()
+                    .map(|(slot, (source, place))| Feed { slot: slot as u8 + 1, source, place })
+                    .collect();
+                self.push(side, round, index as u32 + 1, Vec::new(), feeds)
+            })
+            .collect()
+    }
+}
+
+const TOP: [u32; 2] = [1, 2];
+const BOTTOM: [u32; 2] = [3, 4];
+
+// Snake seeding keeps the field balanced: seed 1 and the last seed of the first pass meet in the same race.
+fn seed_first_round(seeds: &[i64], races: usize) -> Vec<Vec<i64>> {
+    let mut first_round = vec![Vec::new(); races];
+
+    for (index, pilot_id) in seeds.iter().enumerate() {
+        let pass = index / races;
+        let position = if pass % 2 == 0 { index % races } else { races - 1 - index % races };
+        first_round[position].push(*pilot_id);
+    }
+
+    first_round
+}
+
+pub fn plan(bracket_type: BracketType, seeds: &[i64]) -> Vec<PlannedRace> {
+    let first_round_races = ((seeds.len() + PILOTS_PER_RACE - 1) / PILOTS_PER_RACE).max(1).next_power_of_two();
+    let mut planner = Planner { races: Vec::new() };
+
+    let mut winners: Vec<Vec<usize>> = vec![seed_first_round(seeds, first_round_races)
+        .into_iter()
+        .enumerate()
+        .map(|(index, pilot_ids)| planner.push(BracketSide::Winners, 1, index as u32 + 1, pilot_ids, Vec::new()))
+        .collect()];
+
+    while winners.last().unwrap().len() > 1 {
+        let sources: Vec<(usize, [u32; 2])> = winners.last().unwrap().iter().map(|race| (*race, TOP)).collect();
+        let round = winners.len() as u32 + 1;
+        winners.push(planner.merge(BracketSide::Winners, round, &sources));
+    }
+
+    if bracket_type == BracketType::SingleElimination || first_round_races == 1 {
+        let last = planner.races.len() - 1;
+        planner.races[last].side = BracketSide::Final;
+        return planner.races;
+    }
+
+    // Losers of the first winners round meet each other, afterwards every losers round alternates between
+    // taking in the pilots dropping out of the next winners round and halving the field.
+    let sources: Vec<(usize, [u32; 2])> = winners[0].iter().map(|race| (*race, BOTTOM)).collect();
+    let mut round = 1;
+    let mut losers = planner.merge(BracketSide::Losers, round, &sources);
+
+    for winners_round in winners.iter().skip(1) {
+        if losers.len() > winners_round.len() {
+            round += 1;
+            let sources: Vec<(usize, [u32; 2])> = losers.iter().map(|race| (*race, TOP)).collect();
+            losers = planner.merge(BracketSide::Losers, round, &sources);
+        }
+
+        round += 1;
+        let sources: Vec<(usize, [u32; 2])> = losers.iter()
+            .zip(winners_round.iter())
+            .flat_map(|(losers_race, winners_race)| [(*losers_race, TOP), (*winners_race, BOTTOM)])
+            .collect();
+        losers = planner.merge(BracketSide::Losers, round, &sources);
+    }
+
+    let sources = [(*winners.last().unwrap().first().unwrap(), TOP), (losers[0], TOP)];
+    planner.merge(BracketSide::Final, 1, &sources);
+
+    planner.races
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seeds(count: i64) -> Vec<i64> {
+        (1..=count).collect()
+    }
+
+    fn names(races: &[PlannedRace]) -> Vec<String> {
+        races.iter().map(|race| race.name()).collect()
+    }
+
+    // The sources and places feeding a race, in slot order.
+    fn feeds(race: &PlannedRace) -> Vec<(usize, u32)> {
+        assert!(race.feeds.iter().enumerate().all(|(index, feed)| feed.slot as usize == index + 1));
+        race.feeds.iter().map(|feed| (feed.source, feed.place)).collect()
+    }
+
+    #[test]
+    fn snake_seeds_the_first_round() {
+        assert_eq!(seed_first_round(&seeds(8), 2), vec![vec![1, 4, 5, 8], vec![2, 3, 6, 7]]);
+        assert_eq!(seed_first_round(&seeds(9), 4), vec![vec![1, 8, 9], vec![2, 7], vec![3, 6], vec![4, 5]]);
+    }
+
+    #[test]
+    fn plans_a_single_elimination_for_8_seeds() {
+        let races = plan(BracketType::SingleElimination, &seeds(8));
+
+        assert_eq!(names(&races), vec!["Winners R1 Race 1", "Winners R1 Race 2", "Final"]);
+        assert_eq!(races[0].pilot_ids, vec![1, 4, 5, 8]);
+        assert!(races[2].pilot_ids.is_empty());
+        assert_eq!(feeds(&races[2]), vec![(0, 1), (0, 2), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn plans_a_single_elimination_for_16_seeds() {
+        let races = plan(BracketType::SingleElimination, &seeds(16));
+
+        assert_eq!(races.len(), 7);
+        assert_eq!(feeds(&races[4]), vec![(0, 1), (0, 2), (1, 1), (1, 2)]);
+        assert_eq!(feeds(&races[5]), vec![(2, 1), (2, 2), (3, 1), (3, 2)]);
+        assert_eq!(races[6].side, BracketSide::Final);
+        assert_eq!(feeds(&races[6]), vec![(4, 1), (4, 2), (5, 1), (5, 2)]);
+    }
+
+    #[test]
+    fn plans_a_double_elimination_for_8_seeds() {
+        let races = plan(BracketType::DoubleElimination, &seeds(8));
+
+        assert_eq!(names(&races), vec![
+            "Winners R1 Race 1",
+            "Winners R1 Race 2",
+            "Winners R2 Race 1",
+            "Losers R1 Race 1",
+            "Losers R2 Race 1",
+            "Final",
+        ]);
+        assert_eq!(feeds(&races[3]), vec![(0, 3), (0, 4), (1, 3), (1, 4)]);
+        assert_eq!(feeds(&races[4]), vec![(3, 1), (3, 2), (2, 3), (2, 4)]);
+        assert_eq!(feeds(&races[5]), vec![(2, 1), (2, 2), (4, 1), (4, 2)]);
+    }
+
+    #[test]
+    fn plans_a_double_elimination_for_16_seeds() {
+        let races = plan(BracketType::DoubleElimination, &seeds(16));
+        let losers: Vec<(u32, u32)> = races
+            .iter()
+            .filter(|race| race.side == BracketSide::Losers)
+            .map(|race| (race.round, race.position))
+            .collect();
+
+        assert_eq!(races.len(), 14);
+        assert_eq!(losers, vec![(1, 1), (1, 2), (2, 1), (2, 2), (3, 1), (4, 1)]);
+        assert_eq!(feeds(&races[9]), vec![(7, 1), (7, 2), (4, 3), (4, 4)]);
+        assert_eq!(feeds(&races[10]), vec![(8, 1), (8, 2), (5, 3), (5, 4)]);
+        assert_eq!(feeds(&races[11]), vec![(9, 1), (9, 2), (10, 1), (10, 2)]);
+        assert_eq!(feeds(&races[12]), vec![(11, 1), (11, 2), (6, 3), (6, 4)]);
+        assert_eq!(feeds(&races[13]), vec![(6, 1), (6, 2), (12, 1), (12, 2)]);
+    }
+
+    #[test]
+    fn fills_up_to_a_power_of_two_first_round_races() {
+        let races = plan(BracketType::SingleElimination, &seeds(9));
+        let first_round: Vec<usize> = races
+            .iter()
+            .filter(|race| race.round == 1 && race.side == BracketSide::Winners)
+            .map(|race| race.pilot_ids.len())
+            .collect();
+
+        assert_eq!(first_round, vec![3, 2, 2, 2]);
+        assert_eq!(races.len(), 7);
+    }
+
+    #[test]
+    fn plans_a_single_race_as_the_final() {
+        for bracket_type in [BracketType::SingleElimination, BracketType::DoubleElimination] {
+            let races = plan(bracket_type, &seeds(3));
+
+            assert_eq!(names(&races), vec!["Final"]);
+            assert_eq!(races[0].pilot_ids, vec![1, 2, 3]);
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Band {
+    A,
+    B,
+    E,
+    F,
+    R,
+    L,
+    Dji,
+    HdZero,
+    Walksnail,
+}
+
+const BANDS: [Band; 9] = [Band::A, Band::B, Band::E, Band::F, Band::R, Band::L, Band::Dji, Band::HdZero, Band::Walksnail];
+
+impl Band {
+    pub fn frequencies(&self) -> &'static [u16] {
+        match self {
+            Band::A => &[5865, 5845, 5825, 5805, 5785, 5765, 5745, 5725],
+            Band::B => &[5733, 5752, 5771, 5790, 5809, 5828, 5847, 5866],
+            Band::E => &[5705, 5685, 5665, 5645, 5885, 5905, 5925, 5945],
+            Band::F => &[5740, 5760, 5780, 5800, 5820, 5840, 5860, 5880],
+            Band::R | Band::HdZero => &[5658, 5695, 5732, 5769, 5806, 5843, 5880, 5917],
+            Band::L => &[5362, 5399, 5436, 5473, 5510, 5547, 5584, 5621],
+            Band::Dji | Band::Walksnail => &[5660, 5695, 5735, 5770, 5805, 5839, 5878, 5914],
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Band::A => "A",
+            Band::B => "B",
+            Band::E => "E",
+            Band::F => "F",
+            Band::R => "R",
+            Band::L => "L",
+            Band::Dji => "DJI",
+            Band::HdZero => "HDZ",
+            Band::Walksnail => "WS",
+        }
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = Channel> + '_ {
+        (1..=self.frequencies().len() as u8).map(move |number| Channel { band: *self, number })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum VideoSystem {
+    #[default]
+    Analog,
+    DjiO3,
+    HdZero,
+}
+
+impl VideoSystem {
+    // Channels the pilot's video transmitter can tune to, analog gear is expected to cover the common 5.8GHz bands.
+    pub fn channels(&self) -> Vec<Channel> {
+        let bands: &[Band] = match self {
+            VideoSystem::Analog => &[Band::R, Band::F, Band::E, Band::A, Band::B],
+            VideoSystem::DjiO3 => &[Band::Dji],
+            VideoSystem::HdZero => &[Band::HdZero],
+        };
+
+        bands.iter().flat_map(|band| band.channels()).collect()
+    }
+}
+
+impl fmt::Display for VideoSystem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromSql for VideoSystem {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().and_then(|s| {
+            match s {
+                "Analog" => Ok(VideoSystem::Analog),
+                "DjiO3" => Ok(VideoSystem::DjiO3),
+                "HdZero" => Ok(VideoSystem::HdZero),
+                _ => Err(FromSqlError::InvalidType)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Channel {
+    band: Band,
+    number: u8,
+}
+
+impl Channel {
+    pub fn new(band: Band, number: u8) -> Option<Channel> {
+        if number >= 1 && number as usize <= band.frequencies().len() {
+            Some(Channel { band, number })
+        } else {
+            None
+        }
+    }
+
+    pub fn frequency(&self) -> u16 {
+        self.band.frequencies()[self.number as usize - 1]
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.band.code(), self.number)
+    }
+}
+
+impl FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s.find(|c: char| c.is_ascii_digit()).unwrap_or(s.len());
+        let (code, number) = s.split_at(split);
+
+        let band = BANDS
+            .iter()
+            .find(|band| band.code().eq_ignore_ascii_case(code))
+            .ok_or_else(|| format!("Unknown band in channel '{}'", s))?;
+
+        number
+            .parse()
+            .ok()
+            .and_then(|number| Channel::new(*band, number))
+            .ok_or_else(|| format!("Unknown channel '{}'", s))
+    }
+}
+
+impl Serialize for Channel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Channel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl FromSql for Channel {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().and_then(|s| {
+            s.parse().map_err(|_| FromSqlError::InvalidType)
+        })
+    }
+}
+
+impl ToSql for Channel {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.to_string()))
+    }
+}
+
+// Video receivers can not separate transmitters closer than `min_separation`, and third order products
+// (2 * f1 - f2) landing within `imd_margin` of another pilot's frequency show up as noise in their video.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct InterferenceSettings {
+    pub min_separation: u16,
+    pub imd_margin: u16,
+}
+
+impl Default for InterferenceSettings {
+    fn default() -> Self {
+        InterferenceSettings {
+            min_separation: 30,
+            imd_margin: 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub enum IssueKind {
+    SameFrequency,
+    Adjacent,
+    Intermodulation,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct InterferenceIssue {
+    pub severity: Severity,
+    pub kind: IssueKind,
+    pub channels: Vec<Channel>,
+    pub message: String,
+}
+
+fn distance(a: i32, b: i32) -> u16 {
+    (a - b).unsigned_abs() as u16
+}
+
+pub fn check_lineup(channels: &[Channel], settings: &InterferenceSettings) -> Vec<InterferenceIssue> {
+    let mut issues = Vec::new();
+
+    for (index, a) in channels.iter().enumerate() {
+        for b in &channels[index + 1..] {
+            let separation = distance(a.frequency() as i32, b.frequency() as i32);
+
+            if separation == 0 {
+                issues.push(InterferenceIssue {
+                    severity: Severity::Error,
+                    kind: IssueKind::SameFrequency,
+                    channels: vec![*a, *b],
+                    message: format!("{} and {} share {} MHz", a, b, a.frequency()),
+                });
+            } else if separation < settings.min_separation {
+                issues.push(InterferenceIssue {
+                    severity: Severity::Warning,
+                    kind: IssueKind::Adjacent,
+                    channels: vec![*a, *b],
+                    message: format!("{} and {} are only {} MHz apart", a, b, separation),
+                });
+            }
+        }
+    }
+
+    for (a, b, victim, product) in intermodulation_hits(channels, settings.imd_margin) {
+        issues.push(InterferenceIssue {
+            severity: Severity::Warning,
+            kind: IssueKind::Intermodulation,
+            channels: vec![a, b, victim],
+            message: format!("{} and {} produce intermodulation at {} MHz, next to {}", a, b, product, victim),
+        });
+    }
+
+    issues
+}
+
+fn intermodulation_hits(channels: &[Channel], margin: u16) -> Vec<(Channel, Channel, Channel, i32)> {
+    let mut hits = Vec::new();
+
+    for a in channels {
+        for b in channels {
+            if a == b || a.frequency() == b.frequency() {
+                continue;
+            }
+
+            let product = 2 * a.frequency() as i32 - b.frequency() as i32;
+
+            for victim in channels.iter().filter(|victim| *victim != a && *victim != b) {
+                if distance(product, victim.frequency() as i32) < margin {
+                    hits.push((*a, *b, *victim, product));
+                }
+            }
+        }
+    }
+
+    hits
+}
+
+// Lower is better, every product landing close to a used frequency adds how close it got.
+pub fn imd_score(channels: &[Channel], settings: &InterferenceSettings) -> u32 {
+    intermodulation_hits(channels, settings.imd_margin)
+        .iter()
+        .map(|(_, _, victim, product)| (settings.imd_margin - distance(*product, victim.frequency() as i32)) as u32)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channels(codes: &[&str]) -> Vec<Channel> {
+        codes.iter().map(|code| code.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn creates_only_channels_of_the_band() {
+        assert_eq!(Channel::new(Band::R, 0), None);
+        assert_eq!(Channel::new(Band::R, 9), None);
+        assert_eq!(Channel::new(Band::R, 8).map(|channel| channel.frequency()), Some(5917));
+    }
+
+    #[test]
+    fn parses_channel_codes() {
+        assert_eq!("R1".parse::<Channel>().map(|channel| channel.frequency()), Ok(5658));
+        assert_eq!("dji3".parse::<Channel>().map(|channel| channel.to_string()), Ok("DJI3".to_string()));
+        assert_eq!("X1".parse::<Channel>(), Err("Unknown band in channel 'X1'".to_string()));
+        assert_eq!("R0".parse::<Channel>(), Err("Unknown channel 'R0'".to_string()));
+        assert_eq!("R".parse::<Channel>(), Err("Unknown channel 'R'".to_string()));
+    }
+
+    #[test]
+    fn rejects_invalid_channels_when_deserializing() {
+        assert_eq!(serde_json::from_str::<Channel>("\"F4\"").unwrap(), Channel::new(Band::F, 4).unwrap());
+        assert!(serde_json::from_str::<Channel>("\"F9\"").is_err());
+    }
+
+    #[test]
+    fn accepts_a_clean_lineup() {
+        assert!(check_lineup(&channels(&["R1", "R2", "F2", "F4"]), &InterferenceSettings::default()).is_empty());
+    }
+
+    #[test]
+    fn reports_a_shared_frequency_as_error() {
+        let issues = check_lineup(&channels(&["R1", "HDZ1"]), &InterferenceSettings::default());
+
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert_eq!(issues[0].kind, IssueKind::SameFrequency);
+        assert_eq!(issues[0].message, "R1 and HDZ1 share 5658 MHz");
+    }
+
+    #[test]
+    fn warns_about_adjacent_channels() {
+        let issues = check_lineup(&channels(&["R1", "DJI1"]), &InterferenceSettings::default());
+
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Warning);
+        assert_eq!(issues[0].kind, IssueKind::Adjacent);
+        assert_eq!(issues[0].message, "R1 and DJI1 are only 2 MHz apart");
+    }
+
+    #[test]
+    fn finds_third_order_products_next_to_a_pilot() {
+        let lineup = channels(&["R1", "R3", "R5", "R7"]);
+        let hits: Vec<(String, String, String, i32)> = intermodulation_hits(&lineup, 15)
+            .into_iter()
+            .map(|(a, b, victim, product)| (a.to_string(), b.to_string(), victim.to_string(), product))
+            .collect();
+
+        assert_eq!(hits, vec![
+            ("R3".to_string(), "R1".to_string(), "R5".to_string(), 5806),
+            ("R3".to_string(), "R5".to_string(), "R1".to_string(), 5658),
+            ("R5".to_string(), "R3".to_string(), "R7".to_string(), 5880),
+            ("R5".to_string(), "R7".to_string(), "R3".to_string(), 5732),
+        ]);
+        assert_eq!(imd_score(&lineup, &InterferenceSettings::default()), 60);
+        assert!(check_lineup(&lineup, &InterferenceSettings::default())
+            .iter()
+            .all(|issue| issue.kind == IssueKind::Intermodulation && issue.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn ignores_products_outside_of_the_margin() {
+        let lineup = channels(&["R1", "R3", "R5"]);
+
+        assert_eq!(intermodulation_hits(&lineup, 0), vec![]);
+        assert_eq!(intermodulation_hits(&lineup, 1).len(), 2);
+    }
+}
@@ -2,37 +2,100 @@ use std::fmt;
 use std::fmt::{Formatter, write};
 use std::fs::{File, OpenOptions};
 use std::io::{BufReader, ErrorKind, Write};
+use std::time::{Duration, Instant};
 
 use bson::oid::ObjectId;
 use bson::serde_helpers::serialize_object_id_as_hex_string;
-use chrono::serde::ts_microseconds;
+use chrono::serde::{ts_microseconds, ts_microseconds_option};
 use chrono::{DateTime, Utc};
 use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ValueRef};
-use tauri::Manager;
+use tauri::{AppHandle, Manager};
+use tokio::select;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::oneshot;
-use crate::db::Db;
-use crate::device::Commands;
+use tokio_util::sync::CancellationToken;
+use crate::bracket;
+use crate::bracket::{Bracket, BracketSeeding, NewBracketDto};
+use crate::channels;
+use crate::channels::{Channel, InterferenceIssue, InterferenceSettings, Severity, VideoSystem};
+use crate::db::{Db, DbError};
+use crate::device::{get_available_devices, Commands, ConnectionState, ConnectionStatus, DeviceControl, DeviceError, DeviceRequest, DeviceSelection, PortInfo};
+use crate::events;
+use crate::frequency_assignment;
+use crate::events::{AppEvent, LapRecordedEvent, LapsCorrectedEvent, LeaderboardUpdatedEvent, RaceStatusChangedEvent, RacesUpdatedEvent, RssiBatchEvent, RssiSample};
+use crate::heat_generator;
+use crate::heat_generator::HeatPlanDto;
+use crate::lap_detection::{DetectionSettings, LapDetector};
+use crate::leaderboard;
+use crate::leaderboard::LeaderboardEntry;
+use crate::protocol::DeviceEvent;
+use crate::race_engine::RaceFormat;
+use crate::standings;
+use crate::standings::{ConsecutiveRanking, PointsSettings, Standing, TieBreak};
+use crate::start_sequence;
+use crate::start_sequence::{RaceClock, StartPhase, StartSequenceSettings};
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize, PartialEq)]
 pub struct Pilot {
     pub id: i64,
     pub name: String,
+    pub video_system: VideoSystem,
+    pub profile_id: Option<i64>,
+    pub preferred_channel: Option<Channel>,
 }
 
 impl Pilot {
-    pub fn new(id: i64, name: String) -> Pilot {
-        Pilot { id, name }
+    pub fn new(id: i64, name: String, video_system: VideoSystem, profile_id: Option<i64>, preferred_channel: Option<Channel>) -> Pilot {
+        Pilot { id, name, video_system, profile_id, preferred_channel }
     }
 }
 
+// Adds a registered pilot to the event roster, pilots added by name are looked up or registered by callsign.
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct NewPilotDto {
     pub race_event_id: i64,
     pub name: String,
+    #[serde(default)]
+    pub video_system: VideoSystem,
+    pub profile_id: Option<i64>,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct PilotProfile {
+    pub id: i64,
+    pub callsign: String,
+    pub real_name: Option<String>,
+    pub country: Option<String>,
+    pub team: Option<String>,
+    pub video_system: VideoSystem,
+    pub preferred_channel: Option<Channel>,
+    pub transponder: Option<String>,
+    pub phonetic_name: Option<String>,
+    pub avatar_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct NewPilotProfileDto {
+    pub callsign: String,
+    pub real_name: Option<String>,
+    pub country: Option<String>,
+    pub team: Option<String>,
+    pub video_system: VideoSystem,
+    pub preferred_channel: Option<Channel>,
+    pub transponder: Option<String>,
+    pub phonetic_name: Option<String>,
+    pub avatar_path: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PilotVideoSystemDto {
+    pub race_event_id: i64,
+    pub pilot_id: i64,
+    pub video_system: VideoSystem,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq)]
 pub enum RaceStatus {
     New,
     InProgress,
@@ -46,6 +109,19 @@ impl fmt::Display for RaceStatus {
     }
 }
 
+impl RaceStatus {
+    pub fn transition(&self, transition: RaceTransition) -> Option<RaceStatus> {
+        match (self, transition) {
+            (RaceStatus::New, RaceTransition::Start) => Some(RaceStatus::InProgress),
+            (RaceStatus::InProgress, RaceTransition::Stop) => Some(RaceStatus::Finished),
+            (RaceStatus::InProgress, RaceTransition::Interrupt) => Some(RaceStatus::Interrupted),
+            (RaceStatus::Interrupted, RaceTransition::Resume) => Some(RaceStatus::InProgress),
+            (RaceStatus::Interrupted, RaceTransition::Finish) => Some(RaceStatus::Finished),
+            _ => None,
+        }
+    }
+}
+
 impl FromSql for RaceStatus {
     fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
         value.as_str().and_then(|s| {
@@ -60,41 +136,181 @@ impl FromSql for RaceStatus {
     }
 }
 
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq)]
+pub enum RaceTransition {
+    Start,
+    Stop,
+    Interrupt,
+    Resume,
+    Finish,
+}
+
+impl fmt::Display for RaceTransition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub enum LapSource {
+    Device,
+    Manual,
+}
+
+impl fmt::Display for LapSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromSql for LapSource {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().and_then(|s| {
+            match s {
+                "Device" => Ok(LapSource::Device),
+                "Manual" => Ok(LapSource::Manual),
+                _ => Err(FromSqlError::InvalidType)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Lap {
+    pub id: i64,
+    pub heat_id: i64,
+    pub no: u32,
+    pub crossed_at: i64,
+    pub lap_time: i64,
+    pub source: LapSource,
+    pub deleted: bool,
+}
+
+impl Lap {
+    pub fn new(id: i64, heat_id: i64, no: u32, crossed_at: i64, lap_time: i64, source: LapSource, deleted: bool) -> Lap {
+        Lap {
+            id, heat_id, no, crossed_at, lap_time, source, deleted
+        }
+    }
+
+    // The first crossing only ends the run from the start to the gate, it is stored as lap 0 and is not a full lap.
+    pub fn is_full_lap(&self) -> bool {
+        !self.deleted && self.no > 0
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Heat {
-    id: i64,
-    no: u8,
-    channel: String,
-    pilot_id: i64,
+    pub id: i64,
+    pub no: u8,
+    pub channel: Channel,
+    pub pilot_id: i64,
+    pub laps: Vec<Lap>,
 }
 
 impl Heat {
-    pub fn new(id: i64, no: u8, channel: String, pilot_id: i64) -> Heat {
+    pub fn new(id: i64, no: u8, channel: Channel, pilot_id: i64, laps: Vec<Lap>) -> Heat {
         Heat {
-            id, no, channel, pilot_id
+            id, no, channel, pilot_id, laps
         }
     }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Race {
-    id: i64,
-    name: String,
-    status: RaceStatus,
-    heats: Vec<Heat>,
+    pub id: i64,
+    pub name: String,
+    pub status: RaceStatus,
+    pub heats: Vec<Heat>,
+    pub format: RaceFormat,
+    #[serde(with = "ts_microseconds_option")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(with = "ts_microseconds_option")]
+    pub finished_at: Option<DateTime<Utc>>,
+    pub round_id: Option<i64>,
 }
 
 impl Race {
-    pub fn new(id: i64, name: String, status: RaceStatus, heats: Vec<Heat>) -> Race {
-        Race {id, name, status, heats}
+    pub fn new(
+        id: i64,
+        name: String,
+        status: RaceStatus,
+        heats: Vec<Heat>,
+        format: RaceFormat,
+        started_at: Option<DateTime<Utc>>,
+        finished_at: Option<DateTime<Utc>>,
+        round_id: Option<i64>,
+    ) -> Race {
+        Race {id, name, status, heats, format, started_at, finished_at, round_id}
     }
 }
 
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct RaceRefDto {
+    pub race_event_id: i64,
+    pub race_id: i64,
+}
+
+// Without `crossed_at` the lap is recorded at the current race time, which needs the race to be running.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct NewLapDto {
+    pub race_event_id: i64,
+    pub race_id: i64,
+    pub heat_id: i64,
+    pub crossed_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct LapRefDto {
+    pub race_event_id: i64,
+    pub race_id: i64,
+    pub lap_id: i64,
+}
+
+// Without `crossed_at` the missed crossing is put in the middle of the lap.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SplitLapDto {
+    pub race_event_id: i64,
+    pub race_id: i64,
+    pub lap_id: i64,
+    pub crossed_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    #[serde(with = "ts_microseconds")]
+    pub created_at: DateTime<Utc>,
+    pub actor: String,
+    pub action: String,
+    pub race_id: Option<i64>,
+    pub subject: Option<String>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AuditLogFilterDto {
+    pub race_event_id: i64,
+    pub race_id: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewAuditEntry {
+    pub actor: String,
+    pub action: String,
+    pub race_id: Option<i64>,
+    pub subject: Option<String>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct NewHeatDto {
     pub no: u8,
     pub pilot_id: i64,
-    pub channel: String,
+    #[serde(default)]
+    pub channel: Option<Channel>,
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -102,6 +318,93 @@ pub struct NewRaceDto {
     pub name: String,
     pub heats: Vec<NewHeatDto>,
     pub race_event_id: i64,
+    #[serde(default)]
+    pub format: RaceFormat,
+    #[serde(default)]
+    pub round_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq)]
+pub enum RoundType {
+    Practice,
+    Qualifying,
+    Finals,
+}
+
+impl fmt::Display for RoundType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromSql for RoundType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().and_then(|s| {
+            match s {
+                "Practice" => Ok(RoundType::Practice),
+                "Qualifying" => Ok(RoundType::Qualifying),
+                "Finals" => Ok(RoundType::Finals),
+                _ => Err(FromSqlError::InvalidType)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Round {
+    pub id: i64,
+    pub class_id: i64,
+    pub no: u32,
+    pub name: String,
+    pub round_type: RoundType,
+}
+
+impl Round {
+    pub fn new(id: i64, class_id: i64, no: u32, name: String, round_type: RoundType) -> Round {
+        Round { id, class_id, no, name, round_type }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Class {
+    pub id: i64,
+    pub name: String,
+    pub pilot_ids: Vec<i64>,
+    pub rounds: Vec<Round>,
+}
+
+impl Class {
+    pub fn new(id: i64, name: String, pilot_ids: Vec<i64>, rounds: Vec<Round>) -> Class {
+        Class { id, name, pilot_ids, rounds }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct NewClassDto {
+    pub race_event_id: i64,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ClassPilotsDto {
+    pub race_event_id: i64,
+    pub class_id: i64,
+    pub pilot_ids: Vec<i64>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct NewRoundDto {
+    pub race_event_id: i64,
+    pub class_id: i64,
+    pub name: String,
+    pub round_type: RoundType,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct RaceFilterDto {
+    pub race_event_id: i64,
+    pub class_id: Option<i64>,
+    pub round_id: Option<i64>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -157,12 +460,40 @@ pub struct NewRaceEventDto {
 pub struct RaceEventDetailsDto {
     pilots: Vec<Pilot>,
     races: Vec<Race>,
+    classes: Vec<Class>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EventStandingsDto {
+    settings: PointsSettings,
+    standings: Vec<Standing>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ConsecutiveRankingDto {
+    pub race_event_id: i64,
+    pub laps: u32,
+    pub class_id: Option<i64>,
+    pub round_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PointsSettingsDto {
+    pub race_event_id: i64,
+    pub settings: PointsSettings,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct NodeDetectionSettingsDto {
+    pub node: u8,
+    pub settings: DetectionSettings,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct State {
     upcoming_races: Vec<Race>,
     current_race: Option<Race>,
+    current_race_event_id: Option<i64>,
     pilots: Vec<Pilot>,
     race_events: Vec<RaceEvent>,
 }
@@ -172,6 +503,7 @@ impl State {
         State {
             upcoming_races: Vec::new(),
             current_race: None,
+            current_race_event_id: None,
             pilots: Vec::new(),
             race_events,
         }
@@ -184,11 +516,58 @@ pub struct InvokeRequest<T, K> {
     response_tx: oneshot::Sender<Result<K, ErrorMessage>>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub enum ErrorCode {
+    InvalidRequest,
+    NotFound,
+    Conflict,
+    Device,
+    DatabaseBusy,
+    DatabaseVersion,
+    Database,
+    Internal,
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct ErrorMessage {
+    code: ErrorCode,
     message: String,
 }
 
+impl ErrorMessage {
+    // Commands can still be invoked when the core task is not running, e.g. after the database failed to open.
+    pub fn core_stopped() -> ErrorMessage {
+        ErrorMessage {
+            code: ErrorCode::Internal,
+            message: "The race core is not running, restart the app".to_string(),
+        }
+    }
+}
+
+impl From<DeviceError> for ErrorMessage {
+    fn from(error: DeviceError) -> Self {
+        ErrorMessage {
+            code: ErrorCode::Device,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<DbError> for ErrorMessage {
+    fn from(error: DbError) -> Self {
+        let code = match error {
+            DbError::Busy => ErrorCode::DatabaseBusy,
+            DbError::NewerVersion { .. } => ErrorCode::DatabaseVersion,
+            DbError::Invalid(_) | DbError::Sqlite(_) => ErrorCode::Database,
+        };
+
+        ErrorMessage {
+            code,
+            message: error.to_string(),
+        }
+    }
+}
+
 impl<T, K> InvokeRequest<T, K> {
     pub fn new(
         body: T,
@@ -214,85 +593,1541 @@ pub enum Actions {
     CreateRaceEvent(InvokeRequest<NewRaceEventDto, RaceEvent>),
     RemoveRaceEvent(InvokeRequest<i64, ()>),
     AddPilot(InvokeRequest<NewPilotDto, Pilot>),
+    AddPilotProfile(InvokeRequest<NewPilotProfileDto, PilotProfile>),
+    UpdatePilotProfile(InvokeRequest<PilotProfile, PilotProfile>),
+    FindPilotProfiles(InvokeRequest<(), Vec<PilotProfile>>),
+    ImportPilots(InvokeRequest<i64, Vec<PilotProfile>>),
     AddRace(InvokeRequest<NewRaceDto, Race>),
-    StartRace(InvokeRequest<(), ()>),
+    StartRace(InvokeRequest<RaceRefDto, Race>),
+    StopRace(InvokeRequest<RaceRefDto, Race>),
+    InterruptRace(InvokeRequest<RaceRefDto, Race>),
+    ResumeRace(InvokeRequest<RaceRefDto, Race>),
+    FinishRace(InvokeRequest<RaceRefDto, Race>),
+    SendCommand(InvokeRequest<Commands, DeviceEvent>),
+    SetDetectionSettings(InvokeRequest<NodeDetectionSettingsDto, ()>),
+    SetStartSequenceSettings(InvokeRequest<StartSequenceSettings, ()>),
+    ListDevices(InvokeRequest<(), Vec<PortInfo>>),
+    ConnectDevice(InvokeRequest<DeviceSelection, ConnectionStatus>),
+    DisconnectDevice(InvokeRequest<(), ConnectionStatus>),
+    GetDeviceStatus(InvokeRequest<(), ConnectionStatus>),
+    GetLeaderboard(InvokeRequest<RaceRefDto, Vec<LeaderboardEntry>>),
+    GetEventStandings(InvokeRequest<RaceFilterDto, EventStandingsDto>),
+    SetPointsSettings(InvokeRequest<PointsSettingsDto, ()>),
+    GetConsecutiveRanking(InvokeRequest<ConsecutiveRankingDto, Vec<ConsecutiveRanking>>),
+    GenerateHeats(InvokeRequest<HeatPlanDto, Vec<Race>>),
+    CreateBracket(InvokeRequest<NewBracketDto, Bracket>),
+    GetBrackets(InvokeRequest<i64, Vec<Bracket>>),
+    AddClass(InvokeRequest<NewClassDto, Class>),
+    SetClassPilots(InvokeRequest<ClassPilotsDto, Class>),
+    AddRound(InvokeRequest<NewRoundDto, Round>),
+    FindRaces(InvokeRequest<RaceFilterDto, Vec<Race>>),
+    AddLap(InvokeRequest<NewLapDto, Vec<Lap>>),
+    DeleteLap(InvokeRequest<LapRefDto, Vec<Lap>>),
+    RestoreLap(InvokeRequest<LapRefDto, Vec<Lap>>),
+    SplitLap(InvokeRequest<SplitLapDto, Vec<Lap>>),
+    MergeLaps(InvokeRequest<LapRefDto, Vec<Lap>>),
+    FindAuditLog(InvokeRequest<AuditLogFilterDto, Vec<AuditEntry>>),
+    GetOperator(InvokeRequest<(), String>),
+    SetOperator(InvokeRequest<String, String>),
+    CheckChannels(InvokeRequest<Vec<Channel>, Vec<InterferenceIssue>>),
+    SetPilotVideoSystem(InvokeRequest<PilotVideoSystemDto, Pilot>),
+    AssignChannels(InvokeRequest<RaceRefDto, Race>),
 }
 
-pub async fn update_state(state: &mut State, mut rx: Receiver<Actions>, device_tx: Sender<Commands>) {
-    let db = Db::new("db".to_string());
+struct PendingStart {
+    race_event_id: i64,
+    race: Race,
+    armed_at: Instant,
+    cancel: CancellationToken,
+    invoke_request: InvokeRequest<RaceRefDto, Race>,
+}
 
-    while let Some(action) = rx.recv().await {
-        dbg!(&action, &state);
-        match action {
-            Actions::Init(invoke_request) => {
-                invoke_request.response_tx.send(Ok(state.clone())).unwrap();
-            }
-            Actions::LoadRaceEvent(invoke_request) => {
-                let db = Db::new(invoke_request.body.to_string());
+struct Runtime {
+    device_tx: Sender<DeviceRequest>,
+    device_control_tx: Sender<DeviceControl>,
+    app_handle: AppHandle,
+    lap_detector: LapDetector,
+    software_detection: bool,
+    clock: RaceClock,
+    start_sequence_settings: StartSequenceSettings,
+    pending_start: Option<PendingStart>,
+    go_tx: Sender<(i64, Instant)>,
+    rssi_samples: Vec<RssiSample>,
+    operator: String,
+}
+
+pub async fn update_state(
+    state: &mut State,
+    db: Db,
+    mut rx: Receiver<Actions>,
+    device_tx: Sender<DeviceRequest>,
+    device_control_tx: Sender<DeviceControl>,
+    mut device_events_rx: Receiver<DeviceEvent>,
+    app_handle: AppHandle,
+) {
+    let (go_tx, mut go_rx) = channel(1);
+    let mut ticker = tokio::time::interval(Duration::from_millis(100));
 
-                let pilots = db.find_pilots();
-                let races = db.find_races_with_heats();
+    let mut runtime = Runtime {
+        device_tx,
+        device_control_tx,
+        app_handle,
+        lap_detector: LapDetector::default(),
+        software_detection: false,
+        clock: RaceClock::default(),
+        start_sequence_settings: StartSequenceSettings::default(),
+        pending_start: None,
+        go_tx,
+        rssi_samples: Vec::new(),
+        operator: db.find_operator().ok().flatten().unwrap_or_else(|| DEFAULT_OPERATOR.to_string()),
+    };
 
-                invoke_request.response_tx
-                    .send(Ok(RaceEventDetailsDto {pilots, races}))
-                    .unwrap();
+    loop {
+        select! {
+            Some(action) = rx.recv() => handle_action(state, &db, &mut runtime, action).await,
+            Some(event) = device_events_rx.recv() => handle_device_event(state, &mut runtime, event).await,
+            Some((race_id, go_at)) = go_rx.recv() => handle_go(state, &mut runtime, race_id, go_at),
+            _ = ticker.tick() => {
+                flush_rssi_samples(&mut runtime);
+                check_race_end(state, &mut runtime).await;
             }
-            Actions::CreateRaceEvent(invoke_request) => {
-                if invoke_request.body.name.len() > 0 {
-                    let new_race_event = db.insert_race(invoke_request.body.name.clone(), Utc::now(), RaceEventType::Local);
-                    state.race_events.push(new_race_event.clone());
-
-                    invoke_request
-                        .response_tx
-                        .send(Ok(new_race_event.clone()))
-                        .unwrap();
-                } else {
-                    invoke_request
-                        .response_tx
-                        .send(Err(ErrorMessage {
-                            message: format!("Missing 'name' property in RaceEvent").into(),
-                        }))
-                        .unwrap();
-                }
+            else => break,
+        }
+    }
+}
+
+async fn send_command(device_tx: &Sender<DeviceRequest>, command: Commands) -> Result<DeviceEvent, ErrorMessage> {
+    // The device task answers one command at a time, so this allows for one queued command ahead of ours.
+    let timeout = command.ack_timeout() * 2;
+    let (request, receiver) = DeviceRequest::new(command.clone());
+    device_tx.send(request).await.map_err(|_| DeviceError::NotConnected)?;
+
+    let response = tokio::time::timeout(timeout, receiver).await.map_err(|_| DeviceError::Timeout(command))?;
+
+    Ok(response.map_err(|_| DeviceError::NotConnected)??)
+}
+
+async fn control_device<K>(
+    device_control_tx: &Sender<DeviceControl>,
+    control: impl FnOnce(oneshot::Sender<K>) -> DeviceControl,
+) -> Result<K, ErrorMessage> {
+    let (sender, receiver) = oneshot::channel();
+    device_control_tx.send(control(sender)).await.map_err(|_| DeviceError::NotConnected)?;
+
+    Ok(receiver.await.map_err(|_| DeviceError::NotConnected)?)
+}
+
+fn find_transition(state: &State, race_ref: &RaceRefDto, transition: RaceTransition) -> Result<(Db, Race, RaceStatus), ErrorMessage> {
+    let db = Db::event(race_ref.race_event_id)?;
+
+    let race = db.find_race_with_heats(race_ref.race_id)?.ok_or(ErrorMessage {
+        code: ErrorCode::NotFound,
+        message: format!("Race with id '{}' does not exist", race_ref.race_id),
+    })?;
+
+    if let Some(current_race) = &state.current_race {
+        if current_race.id != race.id {
+            return Err(ErrorMessage {
+                code: ErrorCode::Conflict,
+                message: format!("Race '{}' is already running", current_race.name),
+            });
+        }
+    }
+
+    let status = race.status.transition(transition).ok_or(ErrorMessage {
+        code: ErrorCode::Conflict,
+        message: format!("Can not {} race '{}' with status '{}'", transition.to_string().to_lowercase(), race.name, race.status),
+    })?;
+
+    Ok((db, race, status))
+}
+
+fn commit_transition(state: &mut State, runtime: &Runtime, db: &mut Db, race_event_id: i64, mut race: Race, status: RaceStatus) -> Result<Race, ErrorMessage> {
+    let now = Utc::now();
+
+    if race.status == RaceStatus::New {
+        race.started_at = Some(now);
+    }
+
+    if status == RaceStatus::Finished {
+        race.finished_at = Some(now);
+    }
+
+    let previous_status = race.status;
+    race.status = status;
+    db.audited(|db| -> Result<_, DbError> {
+        db.update_race_status(race.id, race.status, race.started_at, race.finished_at)?;
+        Ok(((), audit_entry(
+            runtime,
+            "race_status_changed",
+            Some(race.id),
+            format!("race:{}", race.id),
+            serde_json::to_value(previous_status).ok(),
+            serde_json::to_value(race.status).ok(),
+        )))
+    })?;
+
+    state.upcoming_races.retain(|upcoming_race| upcoming_race.id != race.id);
+
+    if race.status == RaceStatus::Finished {
+        state.current_race = None;
+        state.current_race_event_id = None;
+    } else {
+        state.current_race = Some(race.clone());
+        state.current_race_event_id = Some(race_event_id);
+    }
+
+    events::emit(&runtime.app_handle, AppEvent::RaceStatusChanged(RaceStatusChangedEvent {
+        race_event_id,
+        race: race.clone(),
+    }));
+
+    Ok(race)
+}
+
+async fn start_race(state: &mut State, runtime: &mut Runtime, invoke_request: InvokeRequest<RaceRefDto, Race>) {
+    if let Some(pending_start) = &runtime.pending_start {
+        let message = format!("Race '{}' is already starting", pending_start.race.name);
+        invoke_request.response_tx.send(Err(ErrorMessage { code: ErrorCode::Conflict, message })).unwrap_or(());
+        return;
+    }
+
+    let race = match find_transition(state, &invoke_request.body, RaceTransition::Start) {
+        Ok((_, race, _)) => race,
+        Err(error) => {
+            invoke_request.response_tx.send(Err(error)).unwrap_or(());
+            return;
+        }
+    };
+
+    let status = match control_device(&runtime.device_control_tx, DeviceControl::Status).await {
+        Ok(status) => status,
+        Err(error) => {
+            invoke_request.response_tx.send(Err(error)).unwrap_or(());
+            return;
+        }
+    };
+    let connected = status.state == ConnectionState::Connected;
+
+    // Races can be run without a timer or with video receivers tuned by hand, laps are then added manually.
+    if !connected {
+        println!("No timer connected, laps of race '{}' have to be added by hand", race.name);
+    } else if status.capabilities.as_ref().map_or(false, |capabilities| capabilities.frequency_control) {
+        for command in frequency_assignment::frequency_commands(&race.heats) {
+            if let Err(error) = send_command(&runtime.device_tx, command).await {
+                println!("Can not set the timer frequency: {}", error.message);
             }
-            Actions::AddPilot(invoke_request) => {
-                if state.pilots.iter().any(|x| x.name == invoke_request.body.name) {
-                    invoke_request
-                        .response_tx
-                        .send(Err(ErrorMessage {
-                            message: format!(
-                                "Pilot with name '{}' already exists",
-                                invoke_request.body.name
-                            )
-                            .into(),
-                        }))
-                        .unwrap();
-                } else {
-                    let db = Db::new(invoke_request.body.race_event_id.to_string());
-                    let new_pilot = db.insert_pilot(invoke_request.body.name);
-                    state.pilots.push(new_pilot.clone());
-                    invoke_request
-                        .response_tx
-                        .send(Ok(new_pilot))
-                        .unwrap();
-                }
+        }
+    } else {
+        println!("Timer has no frequency control, tune its nodes by hand");
+    }
+
+    // The timer starts counting once it reads the command, not when it acknowledges it.
+    let armed_at = Instant::now();
+    if connected {
+        if let Err(error) = send_command(&runtime.device_tx, Commands::StartRace).await {
+            invoke_request.response_tx.send(Err(error)).unwrap_or(());
+            return;
+        }
+    }
+
+    // Timers that detect laps themselves report them as lap events, their RSSI stream is only kept for the trace.
+    runtime.software_detection = connected && status.capabilities.as_ref().map_or(false, |capabilities| !capabilities.lap_detection);
+    runtime.lap_detector.reset();
+
+    let cancel = CancellationToken::new();
+    let settings = runtime.start_sequence_settings;
+    let app_handle = runtime.app_handle.clone();
+    let go_tx = runtime.go_tx.clone();
+    let race_id = race.id;
+    let sequence_cancel = cancel.clone();
+
+    tauri::async_runtime::spawn(async move {
+        if let Some(go_at) = start_sequence::run(settings, race_id, app_handle, sequence_cancel).await {
+            go_tx.send((race_id, go_at)).await.unwrap_or(());
+        }
+    });
+
+    runtime.pending_start = Some(PendingStart {
+        race_event_id: invoke_request.body.race_event_id,
+        race,
+        armed_at,
+        cancel,
+        invoke_request,
+    });
+}
+
+fn handle_go(state: &mut State, runtime: &mut Runtime, race_id: i64, go_at: Instant) {
+    let pending_start = match runtime.pending_start.take() {
+        Some(pending_start) if pending_start.race.id == race_id => pending_start,
+        pending_start => {
+            runtime.pending_start = pending_start;
+            return;
+        }
+    };
+
+    runtime.clock = RaceClock::start(pending_start.armed_at, go_at);
+
+    let race = Db::event(pending_start.race_event_id)
+        .map_err(ErrorMessage::from)
+        .and_then(|mut db| commit_transition(state, runtime, &mut db, pending_start.race_event_id, pending_start.race, RaceStatus::InProgress));
+
+    pending_start.invoke_request.response_tx.send(race).unwrap_or(());
+}
+
+async fn cancel_start(runtime: &mut Runtime) -> Option<Race> {
+    let pending_start = runtime.pending_start.take()?;
+    pending_start.cancel.cancel();
+
+    if let Err(error) = send_command(&runtime.device_tx, Commands::AbortRace).await {
+        println!("Can not abort the timer: {}", error.message);
+    }
+
+    start_sequence::emit_phase(&runtime.app_handle, pending_start.race.id, StartPhase::Cancelled);
+
+    pending_start.invoke_request.response_tx
+        .send(Err(ErrorMessage {
+            code: ErrorCode::Conflict,
+            message: format!("Start of race '{}' was cancelled", pending_start.race.name),
+        }))
+        .unwrap_or(());
+
+    Some(pending_start.race)
+}
+
+async fn transition_race(
+    state: &mut State,
+    runtime: &mut Runtime,
+    race_ref: RaceRefDto,
+    transition: RaceTransition,
+) -> Result<Race, ErrorMessage> {
+    if transition == RaceTransition::Stop {
+        if let Some(pending_start) = &runtime.pending_start {
+            if pending_start.race.id == race_ref.race_id {
+                return Ok(cancel_start(runtime).await.unwrap());
             }
-            Actions::AddRace(invoke_request) => {
-                let mut db = Db::new(invoke_request.body.race_event_id.to_string());
-                let new_race = db.insert_race_with_heats(invoke_request.body);
-                state.upcoming_races.push(new_race.clone());
-                invoke_request.response_tx.send(Ok(new_race)).unwrap();
+        }
+    }
+
+    let (mut db, race, status) = find_transition(state, &race_ref, transition)?;
+
+    match transition {
+        RaceTransition::Start => unreachable!("Races are started through the start sequence"),
+        RaceTransition::Resume => {
+            runtime.clock.resume(Instant::now());
+            if let Err(error) = send_command(&runtime.device_tx, Commands::StartRace).await {
+                println!("Can not restart the timer: {}", error.message);
             }
-            Actions::RemoveRaceEvent(invoke_request) => {
-                state.race_events.remove(state.race_events.iter().position(|x| x.id == invoke_request.body).unwrap());
-                db.remove_race_event(invoke_request.body);
-                invoke_request.response_tx.send(Ok(())).unwrap();
+        }
+        RaceTransition::Stop | RaceTransition::Interrupt => {
+            runtime.clock.pause(Instant::now());
+            if let Err(error) = send_command(&runtime.device_tx, Commands::StopRace).await {
+                println!("Can not stop the timer: {}", error.message);
             }
-            Actions::StartRace(invoke_request) => {
-                device_tx.send(Commands::StartRace).await.unwrap();
-                invoke_request.response_tx.send(Ok(())).unwrap();
+            for heat in &race.heats {
+                db.update_heat_rssi_raw(heat.id, runtime.lap_detector.rssi_raw(heat.no))?;
             }
         }
-        dbg!(&state);
+        RaceTransition::Finish => (),
+    }
+
+    let race = commit_transition(state, runtime, &mut db, race_ref.race_event_id, race, status)?;
+
+    if race.status == RaceStatus::Finished {
+        if let Err(error) = advance_bracket(state, runtime, &mut db, race_ref.race_event_id, &race) {
+            println!("Can not advance the bracket: {}", error);
+        }
+    }
+
+    Ok(race)
+}
+
+fn advance_bracket(state: &mut State, runtime: &Runtime, db: &mut Db, race_event_id: i64, race: &Race) -> Result<(), DbError> {
+    let places: Vec<(u32, i64)> = leaderboard::compute(&race.heats)
+        .iter()
+        .map(|entry| (entry.position, entry.pilot_id))
+        .collect();
+
+    let pilots = |races: &[Race]| races.iter()
+        .map(|race| (race.id, race.heats.iter().map(|heat| heat.pilot_id).collect::<Vec<i64>>()))
+        .collect::<Vec<(i64, Vec<i64>)>>();
+
+    let mut previous_races: Vec<Race> = Vec::new();
+    for race_id in db.find_fed_race_ids(race.id)? {
+        previous_races.extend(db.find_race_with_heats(race_id)?);
+    }
+
+    if previous_races.is_empty() {
+        return Ok(());
+    }
+
+    let races = db.audited(|db| -> Result<_, DbError> {
+        let mut races: Vec<Race> = Vec::new();
+        for race_id in db.advance_bracket(race.id, &places)? {
+            races.extend(db.find_race_with_heats(race_id)?);
+        }
+
+        let entry = audit_entry(
+            runtime,
+            "bracket_advanced",
+            Some(race.id),
+            format!("race:{}", race.id),
+            serde_json::to_value(pilots(&previous_races)).ok(),
+            serde_json::to_value(pilots(&races)).ok(),
+        );
+
+        Ok((races, entry))
+    })?;
+
+    for race in &races {
+        if let Some(upcoming_race) = state.upcoming_races.iter_mut().find(|upcoming_race| upcoming_race.id == race.id) {
+            *upcoming_race = race.clone();
+        }
+    }
+
+    events::emit(&runtime.app_handle, AppEvent::RacesUpdated(RacesUpdatedEvent { race_event_id, races }));
+
+    Ok(())
+}
+
+fn record_lap(state: &mut State, runtime: &Runtime, node: u8, device_timestamp: u64) {
+    if let Some(pending_start) = &runtime.pending_start {
+        if pending_start.race.heats.iter().any(|heat| heat.no == node) {
+            start_sequence::emit_phase(&runtime.app_handle, pending_start.race.id, StartPhase::FalseStart { node });
+        }
+        return;
+    }
+
+    if let (Some(race), Some(race_event_id)) = (state.current_race.as_mut(), state.current_race_event_id) {
+        if race.status != RaceStatus::InProgress {
+            return;
+        }
+
+        if let Some(heat) = race.heats.iter_mut().find(|heat| heat.no == node) {
+            let crossed_at = runtime.clock.race_time(device_timestamp);
+
+            if crossed_at < runtime.start_sequence_settings.false_start_window as i64 && heat.laps.is_empty() {
+                start_sequence::emit_phase(&runtime.app_handle, race.id, StartPhase::FalseStart { node });
+                return;
+            }
+
+            let recorded = Db::event(race_event_id).and_then(|mut db| {
+                let lap = db.insert_lap(heat.id, crossed_at, LapSource::Device)?;
+                Ok((lap, db.find_laps(heat.id)?))
+            });
+            let lap = match recorded {
+                Ok((lap, laps)) => {
+                    heat.laps = laps;
+                    lap
+                }
+                Err(error) => {
+                    println!("Can not record the lap: {}", error);
+                    return;
+                }
+            };
+
+            events::emit(&runtime.app_handle, AppEvent::LapRecorded(LapRecordedEvent {
+                race_event_id,
+                race_id: race.id,
+                lap,
+                laps: heat.laps.clone(),
+            }));
+
+            events::emit(&runtime.app_handle, AppEvent::LeaderboardUpdated(LeaderboardUpdatedEvent {
+                race_event_id,
+                race_id: race.id,
+                entries: leaderboard::compute(&race.heats),
+            }));
+        }
+    }
+}
+
+const DEFAULT_OPERATOR: &str = "Race director";
+
+// The audit log is the only record of who changed what, so every action changing an event ends up here.
+// Entries are written through `Db::audited`, in the same transaction as the change they describe.
+fn audit_entry(
+    runtime: &Runtime,
+    action: &str,
+    race_id: Option<i64>,
+    subject: String,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+) -> NewAuditEntry {
+    NewAuditEntry {
+        actor: runtime.operator.clone(),
+        action: action.to_string(),
+        race_id,
+        subject: Some(subject),
+        before,
+        after,
+    }
+}
+
+// Timer settings belong to no event, they are logged in every event that can still be raced.
+fn audit_open_events(state: &State, runtime: &Runtime, action: &str, subject: String, before: Option<serde_json::Value>, after: Option<serde_json::Value>) -> Result<(), DbError> {
+    for race_event in &state.race_events {
+        let db = Db::event(race_event.id)?;
+        if !db.is_finished()? {
+            db.insert_audit_entry(&audit_entry(runtime, action, None, subject.clone(), before.clone(), after.clone()))?;
+        }
+    }
+
+    Ok(())
+}
+
+enum LapCorrection {
+    Add { heat_id: i64, crossed_at: Option<i64> },
+    Delete { lap_id: i64 },
+    Restore { lap_id: i64 },
+    Split { lap_id: i64, crossed_at: Option<i64> },
+    Merge { lap_id: i64 },
+}
+
+// Marshals fix what the timer got wrong: every correction is written to the audit log with the laps of the
+// heat before and after it, and the leaderboard of the race is sent again.
+fn correct_laps(state: &mut State, runtime: &Runtime, race_event_id: i64, race_id: i64, correction: LapCorrection) -> Result<Vec<Lap>, ErrorMessage> {
+    let mut db = Db::event(race_event_id)?;
+    let mut race = db.find_race_with_heats(race_id)?.ok_or(ErrorMessage {
+        code: ErrorCode::NotFound,
+        message: format!("Race with id '{}' does not exist", race_id),
+    })?;
+
+    if race.status == RaceStatus::New {
+        return Err(ErrorMessage { code: ErrorCode::Conflict, message: format!("Race '{}' has not started yet", race.name) });
+    }
+
+    let lap = match correction {
+        LapCorrection::Add { .. } => None,
+        LapCorrection::Delete { lap_id }
+        | LapCorrection::Restore { lap_id }
+        | LapCorrection::Split { lap_id, .. }
+        | LapCorrection::Merge { lap_id } => Some(db.find_lap(lap_id)?.ok_or(ErrorMessage {
+            code: ErrorCode::NotFound,
+            message: format!("Lap with id '{}' does not exist", lap_id),
+        })?),
+    };
+    let heat_id = match (&correction, &lap) {
+        (LapCorrection::Add { heat_id, .. }, _) => *heat_id,
+        (_, Some(lap)) => lap.heat_id,
+        (_, None) => unreachable!(),
+    };
+    let heat = race.heats.iter_mut().find(|heat| heat.id == heat_id).ok_or(ErrorMessage {
+        code: ErrorCode::InvalidRequest,
+        message: format!("Heat with id '{}' is not part of race '{}'", heat_id, race.name),
+    })?;
+    let before = heat.laps.clone();
+    let counted: Vec<&Lap> = before.iter().filter(|lap| !lap.deleted).collect();
+
+    let laps = db.audited(|db| {
+        let action = match (correction, lap) {
+            (LapCorrection::Add { crossed_at, .. }, _) => {
+                let is_running = state.current_race.as_ref().map_or(false, |current_race| current_race.id == race_id)
+                    && race.status == RaceStatus::InProgress;
+                let crossed_at = match crossed_at {
+                    Some(crossed_at) => crossed_at,
+                    None if is_running => runtime.clock.elapsed(Instant::now()),
+                    None => return Err(ErrorMessage { code: ErrorCode::Conflict, message: format!("Race '{}' is not running, the lap needs a time", race.name) }),
+                };
+                if crossed_at <= 0 {
+                    return Err(ErrorMessage { code: ErrorCode::InvalidRequest, message: "Lap can not be recorded before the start".to_string() });
+                }
+
+                db.insert_lap(heat_id, crossed_at, LapSource::Manual)?;
+                "lap_added"
+            }
+            (LapCorrection::Delete { lap_id }, Some(lap)) => {
+                if lap.deleted {
+                    return Err(ErrorMessage { code: ErrorCode::Conflict, message: format!("Lap with id '{}' is already deleted", lap_id) });
+                }
+
+                db.remove_lap(lap_id)?;
+                "lap_deleted"
+            }
+            (LapCorrection::Restore { lap_id }, Some(lap)) => {
+                if !lap.deleted {
+                    return Err(ErrorMessage { code: ErrorCode::Conflict, message: format!("Lap with id '{}' is not deleted", lap_id) });
+                }
+
+                db.restore_lap(lap_id)?;
+                "lap_restored"
+            }
+            (LapCorrection::Split { crossed_at, .. }, Some(lap)) => {
+                let previous = counted.iter()
+                    .filter(|other| other.crossed_at < lap.crossed_at)
+                    .map(|other| other.crossed_at)
+                    .max()
+                    .unwrap_or(0);
+                let crossed_at = crossed_at.unwrap_or((previous + lap.crossed_at) / 2);
+
+                if lap.deleted || crossed_at <= previous || crossed_at >= lap.crossed_at {
+                    return Err(ErrorMessage { code: ErrorCode::InvalidRequest, message: format!("Split has to be within lap {}", lap.no) });
+                }
+
+                db.insert_lap(heat_id, crossed_at, LapSource::Manual)?;
+                "lap_split"
+            }
+            (LapCorrection::Merge { lap_id }, Some(lap)) => {
+                if lap.deleted || !counted.iter().any(|other| other.crossed_at > lap.crossed_at) {
+                    return Err(ErrorMessage { code: ErrorCode::InvalidRequest, message: format!("Lap {} has no following lap to merge with", lap.no) });
+                }
+
+                db.remove_lap(lap_id)?;
+                "laps_merged"
+            }
+            (_, None) => unreachable!(),
+        };
+        let laps = db.find_laps(heat_id)?;
+        let entry = audit_entry(runtime, action, Some(race_id), format!("heat:{}", heat_id), serde_json::to_value(&before).ok(), serde_json::to_value(&laps).ok());
+
+        Ok((laps, entry))
+    })?;
+    heat.laps = laps.clone();
+
+    if let Some(current_race) = state.current_race.as_mut().filter(|current_race| current_race.id == race_id) {
+        if let Some(current_heat) = current_race.heats.iter_mut().find(|current_heat| current_heat.id == heat_id) {
+            current_heat.laps = laps.clone();
+        }
+    }
+
+    events::emit(&runtime.app_handle, AppEvent::LapsCorrected(LapsCorrectedEvent {
+        race_event_id,
+        race_id,
+        heat_id,
+        laps: laps.clone(),
+    }));
+
+    events::emit(&runtime.app_handle, AppEvent::LeaderboardUpdated(LeaderboardUpdatedEvent {
+        race_event_id,
+        race_id,
+        entries: leaderboard::compute(&race.heats),
+    }));
+
+    // Corrections can change who advanced, races that have not been flown yet are seeded again.
+    if race.status == RaceStatus::Finished {
+        if let Err(error) = advance_bracket(state, runtime, &mut db, race_event_id, &race) {
+            println!("Can not advance the bracket: {}", error);
+        }
+    }
+
+    Ok(laps)
+}
+
+// RSSI arrives far too often to emit every sample on its own, the UI gets them in batches.
+fn flush_rssi_samples(runtime: &mut Runtime) {
+    if runtime.rssi_samples.is_empty() {
+        return;
+    }
+
+    let samples = std::mem::take(&mut runtime.rssi_samples);
+    events::emit(&runtime.app_handle, AppEvent::RssiBatch(RssiBatchEvent { samples }));
+}
+
+async fn check_race_end(state: &mut State, runtime: &mut Runtime) {
+    let race_ref = match (&state.current_race, state.current_race_event_id) {
+        (Some(race), Some(race_event_id)) if race.status == RaceStatus::InProgress => {
+            let crossings: Vec<Vec<i64>> = race.heats.iter()
+                .map(|heat| heat.laps.iter().filter(|lap| lap.is_full_lap()).map(|lap| lap.crossed_at).collect())
+                .collect();
+
+            if !race.format.is_finished(&crossings, runtime.clock.elapsed(Instant::now())) {
+                return;
+            }
+
+            RaceRefDto { race_event_id, race_id: race.id }
+        }
+        _ => return,
+    };
+
+    if let Err(error) = transition_race(state, runtime, race_ref, RaceTransition::Stop).await {
+        println!("Can not finish the race: {}", error.message);
+    }
+}
+
+async fn handle_device_event(state: &mut State, runtime: &mut Runtime, event: DeviceEvent) {
+    match event {
+        DeviceEvent::Lap { node, timestamp } => {
+            record_lap(state, runtime, node, timestamp);
+            check_race_end(state, runtime).await;
+        }
+        DeviceEvent::Rssi { node, timestamp, rssi } => {
+            runtime.rssi_samples.push(RssiSample { node, timestamp, rssi });
+            let crossing = runtime.lap_detector.feed(node, timestamp, rssi);
+            if let Some(crossing) = crossing.filter(|_| runtime.software_detection) {
+                record_lap(state, runtime, crossing.node, crossing.timestamp);
+                check_race_end(state, runtime).await;
+            }
+        }
+        DeviceEvent::Error { message } => println!("Device error: {}", message),
+        DeviceEvent::Version { version } => println!("Timer version: {}", version),
+        DeviceEvent::Heartbeat { .. } | DeviceEvent::Ack { .. } => (),
+    }
+}
+
+// Races of a round are flown by the pilots of its class, races outside of rounds by everyone in the event.
+fn find_round_roster(db: &Db, round_id: Option<i64>) -> Result<(Vec<Pilot>, Option<i64>), ErrorMessage> {
+    match round_id {
+        Some(round_id) => {
+            let round = db.find_round(round_id)?.ok_or(ErrorMessage {
+                code: ErrorCode::NotFound,
+                message: format!("Round with id '{}' does not exist", round_id),
+            })?;
+            Ok((db.find_class_pilots(round.class_id)?, Some(round.class_id)))
+        }
+        None => Ok((db.find_pilots()?, None)),
+    }
+}
+
+// Warnings are left to the race director, only pilots sharing a frequency make a line-up unusable.
+fn check_channels(channels: &[Channel]) -> Result<(), ErrorMessage> {
+    match channels::check_lineup(channels, &InterferenceSettings::default())
+        .into_iter()
+        .find(|issue| issue.severity == Severity::Error) {
+        Some(issue) => Err(ErrorMessage { code: ErrorCode::InvalidRequest, message: issue.message }),
+        None => Ok(()),
+    }
+}
+
+fn add_pilot(state: &mut State, runtime: &Runtime, db: &Db, new_pilot_dto: NewPilotDto) -> Result<Pilot, ErrorMessage> {
+    let profile = match new_pilot_dto.profile_id {
+        Some(profile_id) => db.find_pilot_profile(profile_id)?.ok_or(ErrorMessage {
+            code: ErrorCode::NotFound,
+            message: format!("Pilot profile with id '{}' does not exist", profile_id),
+        })?,
+        None if new_pilot_dto.name.is_empty() => {
+            return Err(ErrorMessage {
+                code: ErrorCode::InvalidRequest,
+                message: "Missing 'name' property in Pilot".to_string(),
+            });
+        }
+        None => match db.find_pilot_profile_by_callsign(&new_pilot_dto.name)? {
+            Some(profile) => profile,
+            None => db.insert_pilot_profile(&NewPilotProfileDto {
+                callsign: new_pilot_dto.name.clone(),
+                video_system: new_pilot_dto.video_system,
+                ..Default::default()
+            })?,
+        },
+    };
+
+    let mut event_db = Db::event(new_pilot_dto.race_event_id)?;
+    if event_db.find_pilots()?.iter().any(|pilot| pilot.profile_id == Some(profile.id) || pilot.name.eq_ignore_ascii_case(&profile.callsign)) {
+        return Err(ErrorMessage {
+            code: ErrorCode::Conflict,
+            message: format!("Pilot with name '{}' already exists", profile.callsign),
+        });
+    }
+
+    let new_pilot = event_db.audited(|db| -> Result<_, DbError> {
+        let new_pilot = db.insert_pilot(&profile)?;
+        let entry = audit_entry(runtime, "pilot_added", None, format!("pilot:{}", new_pilot.id), None, serde_json::to_value(&new_pilot).ok());
+
+        Ok((new_pilot, entry))
+    })?;
+    state.pilots.push(new_pilot.clone());
+
+    Ok(new_pilot)
+}
+
+fn update_pilot_profile(state: &State, runtime: &Runtime, db: &Db, profile: PilotProfile) -> Result<PilotProfile, ErrorMessage> {
+    if profile.callsign.is_empty() {
+        return Err(ErrorMessage {
+            code: ErrorCode::InvalidRequest,
+            message: "Missing 'callsign' property in PilotProfile".to_string(),
+        });
+    }
+    let previous_profile = db.find_pilot_profile(profile.id)?.ok_or(ErrorMessage {
+        code: ErrorCode::NotFound,
+        message: format!("Pilot profile with id '{}' does not exist", profile.id),
+    })?;
+    if db.find_pilot_profile_by_callsign(&profile.callsign)?.map_or(false, |other| other.id != profile.id) {
+        return Err(ErrorMessage {
+            code: ErrorCode::Conflict,
+            message: format!("Pilot with callsign '{}' already exists", profile.callsign),
+        });
+    }
+
+    db.update_pilot_profile(&profile)?;
+    // Results of finished events keep the pilots as they raced.
+    for race_event in &state.race_events {
+        let mut event_db = Db::event(race_event.id)?;
+        if event_db.is_finished()? || !event_db.find_pilots()?.iter().any(|pilot| pilot.profile_id == Some(profile.id)) {
+            continue;
+        }
+
+        event_db.audited(|event_db| -> Result<_, DbError> {
+            event_db.sync_pilot_profile(&profile)?;
+            Ok(((), audit_entry(
+                runtime,
+                "pilot_profile_updated",
+                None,
+                format!("pilot_profile:{}", profile.id),
+                serde_json::to_value(&previous_profile).ok(),
+                serde_json::to_value(&profile).ok(),
+            )))
+        })?;
+    }
+
+    Ok(profile)
+}
+
+// Heats without a hand-picked channel get one matching the pilot's video system, the hand-picked ones stay.
+fn assign_channels(pilots: &[Pilot], new_race_dto: &mut NewRaceDto) -> Result<(), ErrorMessage> {
+    if new_race_dto.heats.iter().all(|heat| heat.channel.is_some()) {
+        return Ok(());
+    }
+
+    let heat_pilots: Vec<Option<&Pilot>> = new_race_dto.heats
+        .iter()
+        .map(|heat| pilots.iter().find(|pilot| pilot.id == heat.pilot_id))
+        .collect();
+    let candidates: Vec<Vec<Channel>> = new_race_dto.heats
+        .iter()
+        .zip(heat_pilots.iter())
+        .map(|(heat, pilot)| match heat.channel {
+            Some(channel) => vec![channel],
+            None => pilot.map_or(VideoSystem::default(), |pilot| pilot.video_system).channels(),
+        })
+        .collect();
+    let preferred: Vec<Option<Channel>> = heat_pilots
+        .iter()
+        .map(|pilot| pilot.and_then(|pilot| pilot.preferred_channel))
+        .collect();
+
+    let channels = frequency_assignment::assign(&candidates, &preferred, &InterferenceSettings::default()).ok_or(ErrorMessage {
+        code: ErrorCode::InvalidRequest,
+        message: format!("Can not find separate channels for race '{}'", new_race_dto.name),
+    })?;
+
+    for (heat, channel) in new_race_dto.heats.iter_mut().zip(channels) {
+        heat.channel = Some(channel);
+    }
+
+    Ok(())
+}
+
+fn reassign_channels(state: &mut State, runtime: &Runtime, race_ref: RaceRefDto) -> Result<Race, ErrorMessage> {
+    let mut db = Db::event(race_ref.race_event_id)?;
+    let race = db.find_race_with_heats(race_ref.race_id)?.ok_or(ErrorMessage {
+        code: ErrorCode::NotFound,
+        message: format!("Race with id '{}' does not exist", race_ref.race_id),
+    })?;
+
+    if race.status != RaceStatus::New {
+        return Err(ErrorMessage {
+            code: ErrorCode::Conflict,
+            message: format!("Channels of race '{}' can not change once it started", race.name),
+        });
+    }
+
+    let mut new_race_dto = NewRaceDto {
+        name: race.name.clone(),
+        heats: race.heats
+            .iter()
+            .map(|heat| NewHeatDto { no: heat.no, pilot_id: heat.pilot_id, channel: None })
+            .collect(),
+        race_event_id: race_ref.race_event_id,
+        format: race.format.clone(),
+        round_id: race.round_id,
+    };
+    assign_channels(&db.find_pilots()?, &mut new_race_dto)?;
+
+    let channels = |race: &Race| race.heats.iter().map(|heat| (heat.pilot_id, heat.channel)).collect::<Vec<(i64, Channel)>>();
+    let race = db.audited(|db| -> Result<_, DbError> {
+        for (heat, new_heat) in race.heats.iter().zip(new_race_dto.heats.iter()) {
+            if let Some(channel) = new_heat.channel {
+                db.update_heat_channel(heat.id, channel)?;
+            }
+        }
+
+        let updated_race = db.find_race_with_heats(race.id)?.unwrap_or_else(|| race.clone());
+        let entry = audit_entry(
+            runtime,
+            "channels_assigned",
+            Some(race.id),
+            format!("race:{}", race.id),
+            serde_json::to_value(channels(&race)).ok(),
+            serde_json::to_value(channels(&updated_race)).ok(),
+        );
+
+        Ok((updated_race, entry))
+    })?;
+    if let Some(upcoming_race) = state.upcoming_races.iter_mut().find(|upcoming_race| upcoming_race.id == race.id) {
+        *upcoming_race = race.clone();
+    }
+
+    Ok(race)
+}
+
+fn create_bracket(state: &mut State, runtime: &Runtime, new_bracket_dto: NewBracketDto) -> Result<Bracket, ErrorMessage> {
+    if new_bracket_dto.name.is_empty() {
+        return Err(ErrorMessage {
+            code: ErrorCode::InvalidRequest,
+            message: "Missing 'name' property in Bracket".to_string(),
+        });
+    }
+
+    if new_bracket_dto.channels.len() < bracket::PILOTS_PER_RACE {
+        return Err(ErrorMessage {
+            code: ErrorCode::InvalidRequest,
+            message: format!("Bracket races need {} channels", bracket::PILOTS_PER_RACE),
+        });
+    }
+
+    check_channels(&new_bracket_dto.channels[..bracket::PILOTS_PER_RACE])?;
+
+    let mut db = Db::event(new_bracket_dto.race_event_id)?;
+    let (roster, class_id) = find_round_roster(&db, new_bracket_dto.round_id)?;
+
+    let mut seeds: Vec<i64> = match &new_bracket_dto.seeding {
+        BracketSeeding::Standings => standings::compute(&db.find_races(class_id, None)?, &db.find_points_settings()?)
+            .iter()
+            .map(|standing| standing.pilot_id)
+            .collect(),
+        BracketSeeding::Consecutive { laps } => standings::compute_consecutive(&db.find_races(class_id, None)?, *laps)
+            .iter()
+            .filter(|ranking| ranking.time.is_some())
+            .map(|ranking| ranking.pilot_id)
+            .collect(),
+        BracketSeeding::Manual { pilot_ids } => {
+            for (index, pilot_id) in pilot_ids.iter().enumerate() {
+                if pilot_ids[..index].contains(pilot_id) {
+                    return Err(ErrorMessage {
+                        code: ErrorCode::InvalidRequest,
+                        message: format!("Pilot with id '{}' is seeded more than once", pilot_id),
+                    });
+                }
+                if !roster.iter().any(|pilot| pilot.id == *pilot_id) {
+                    return Err(ErrorMessage {
+                        code: ErrorCode::NotFound,
+                        message: format!("Pilot with id '{}' is not in the bracket's roster", pilot_id),
+                    });
+                }
+            }
+            pilot_ids.clone()
+        }
+    };
+
+    if let Some(pilots) = new_bracket_dto.pilots {
+        seeds.truncate(pilots);
+    }
+
+    if seeds.len() < 2 {
+        return Err(ErrorMessage {
+            code: ErrorCode::InvalidRequest,
+            message: "Bracket needs at least 2 seeded pilots".to_string(),
+        });
+    }
+
+    let planned_races = bracket::plan(new_bracket_dto.bracket_type, &seeds);
+    let new_bracket = db.audited(|db| -> Result<_, DbError> {
+        let new_bracket = db.insert_bracket(&new_bracket_dto, &planned_races)?;
+        let entry = audit_entry(runtime, "bracket_created", None, format!("bracket:{}", new_bracket.id), None, serde_json::to_value(&new_bracket).ok());
+
+        Ok((new_bracket, entry))
+    })?;
+
+    for bracket_race in &new_bracket.races {
+        state.upcoming_races.extend(db.find_race_with_heats(bracket_race.race_id)?);
+    }
+
+    Ok(new_bracket)
+}
+
+fn load_race_event(race_event_id: i64) -> Result<RaceEventDetailsDto, ErrorMessage> {
+    let db = Db::event(race_event_id)?;
+
+    let pilots = db.find_pilots()?;
+    let races = db.find_races_with_heats()?;
+    let classes = db.find_classes()?;
+
+    Ok(RaceEventDetailsDto {pilots, races, classes})
+}
+
+fn create_race_event(state: &mut State, db: &Db, runtime: &Runtime, new_race_event_dto: NewRaceEventDto) -> Result<RaceEvent, ErrorMessage> {
+    if new_race_event_dto.name.is_empty() {
+        return Err(ErrorMessage {
+            code: ErrorCode::InvalidRequest,
+            message: "Missing 'name' property in RaceEvent".to_string(),
+        });
+    }
+
+    let new_race_event = db.insert_race(new_race_event_dto.name, Utc::now(), RaceEventType::Local)?;
+    let entry = audit_entry(
+        runtime,
+        "race_event_created",
+        None,
+        format!("race_event:{}", new_race_event.id),
+        None,
+        serde_json::to_value(&new_race_event).ok(),
+    );
+
+    // The event and its log live in different files, an event that can not record its creation is removed again.
+    if let Err(error) = Db::event(new_race_event.id).and_then(|event_db| event_db.insert_audit_entry(&entry)) {
+        db.remove_race_event(new_race_event.id)?;
+        return Err(error.into());
+    }
+    state.race_events.push(new_race_event.clone());
+
+    Ok(new_race_event)
+}
+
+fn remove_race_event(state: &mut State, db: &Db, race_event_id: i64) -> Result<(), ErrorMessage> {
+    let position = state.race_events.iter().position(|race_event| race_event.id == race_event_id).ok_or(ErrorMessage {
+        code: ErrorCode::NotFound,
+        message: format!("Race event with id '{}' does not exist", race_event_id),
+    })?;
+
+    db.remove_race_event(race_event_id)?;
+    state.race_events.remove(position);
+
+    Ok(())
+}
+
+fn add_pilot_profile(db: &Db, new_pilot_profile_dto: NewPilotProfileDto) -> Result<PilotProfile, ErrorMessage> {
+    if new_pilot_profile_dto.callsign.is_empty() {
+        return Err(ErrorMessage {
+            code: ErrorCode::InvalidRequest,
+            message: "Missing 'callsign' property in PilotProfile".to_string(),
+        });
+    }
+    if db.find_pilot_profile_by_callsign(&new_pilot_profile_dto.callsign)?.is_some() {
+        return Err(ErrorMessage {
+            code: ErrorCode::Conflict,
+            message: format!("Pilot with callsign '{}' already exists", new_pilot_profile_dto.callsign),
+        });
+    }
+
+    Ok(db.insert_pilot_profile(&new_pilot_profile_dto)?)
+}
+
+fn import_pilots(db: &Db, runtime: &Runtime, race_event_id: i64) -> Result<Vec<PilotProfile>, ErrorMessage> {
+    let mut event_db = Db::event(race_event_id)?;
+
+    // Profiles created for a failed import stay, importing again links them by callsign.
+    let imported = event_db.audited(|event_db| -> Result<_, DbError> {
+        let mut imported: Vec<PilotProfile> = Vec::new();
+
+        for pilot in event_db.find_pilots()?.into_iter().filter(|pilot| pilot.profile_id.is_none()) {
+            let profile = match db.find_pilot_profile_by_callsign(&pilot.name)? {
+                Some(profile) => profile,
+                None => db.insert_pilot_profile(&NewPilotProfileDto {
+                    callsign: pilot.name.clone(),
+                    video_system: pilot.video_system,
+                    preferred_channel: pilot.preferred_channel,
+                    ..Default::default()
+                })?,
+            };
+            event_db.link_pilot_profile(pilot.id, &profile)?;
+            imported.push(profile);
+        }
+
+        let entry = audit_entry(
+            runtime,
+            "pilots_imported",
+            None,
+            format!("race_event:{}", race_event_id),
+            None,
+            serde_json::to_value(&imported).ok(),
+        );
+
+        Ok((imported, entry))
+    })?;
+
+    Ok(imported)
+}
+
+fn add_race(state: &mut State, runtime: &Runtime, mut new_race_dto: NewRaceDto) -> Result<Race, ErrorMessage> {
+    let channels: Vec<Channel> = new_race_dto.heats.iter().filter_map(|heat| heat.channel).collect();
+    let mut db = Db::event(new_race_dto.race_event_id)?;
+
+    check_channels(&channels)?;
+    assign_channels(&db.find_pilots()?, &mut new_race_dto)?;
+
+    if let Some(round_id) = new_race_dto.round_id {
+        if db.find_round(round_id)?.is_none() {
+            return Err(ErrorMessage {
+                code: ErrorCode::NotFound,
+                message: format!("Round with id '{}' does not exist", round_id),
+            });
+        }
+    }
+
+    let new_race = db.audited(|db| -> Result<_, DbError> {
+        let new_race = db.insert_race_with_heats(new_race_dto)?;
+        let entry = audit_entry(runtime, "race_added", Some(new_race.id), format!("race:{}", new_race.id), None, serde_json::to_value(&new_race).ok());
+
+        Ok((new_race, entry))
+    })?;
+    state.upcoming_races.push(new_race.clone());
+
+    Ok(new_race)
+}
+
+fn generate_heats(state: &mut State, runtime: &Runtime, heat_plan_dto: HeatPlanDto) -> Result<Vec<Race>, ErrorMessage> {
+    heat_plan_dto.validate().map_err(|message| ErrorMessage { code: ErrorCode::InvalidRequest, message })?;
+    if !heat_plan_dto.channels.is_empty() {
+        check_channels(&heat_plan_dto.channels[..heat_plan_dto.nodes as usize])?;
+    }
+
+    let mut db = Db::event(heat_plan_dto.race_event_id)?;
+    let (pilots, class_id) = find_round_roster(&db, heat_plan_dto.round_id)?;
+    let standings = standings::compute(&db.find_races(class_id, None)?, &db.find_points_settings()?);
+    let mut new_race_dtos = heat_generator::generate(&heat_plan_dto, &pilots, &standings);
+
+    new_race_dtos.iter_mut().try_for_each(|new_race_dto| assign_channels(&pilots, new_race_dto))?;
+
+    let subject = heat_plan_dto.round_id.map_or("races".to_string(), |round_id| format!("round:{}", round_id));
+    let new_races = db.audited(|db| -> Result<_, DbError> {
+        let new_races = new_race_dtos
+            .into_iter()
+            .map(|new_race_dto| db.insert_race_with_heats(new_race_dto))
+            .collect::<Result<Vec<Race>, DbError>>()?;
+        let entry = audit_entry(runtime, "heats_generated", None, subject, None, serde_json::to_value(&new_races).ok());
+
+        Ok((new_races, entry))
+    })?;
+    state.upcoming_races.extend(new_races.iter().cloned());
+
+    Ok(new_races)
+}
+
+fn add_class(runtime: &Runtime, new_class_dto: NewClassDto) -> Result<Class, ErrorMessage> {
+    if new_class_dto.name.is_empty() {
+        return Err(ErrorMessage {
+            code: ErrorCode::InvalidRequest,
+            message: "Missing 'name' property in Class".to_string(),
+        });
+    }
+
+    let mut db = Db::event(new_class_dto.race_event_id)?;
+    let new_class = db.audited(|db| -> Result<_, DbError> {
+        let new_class = db.insert_class(new_class_dto.name)?;
+        let entry = audit_entry(runtime, "class_added", None, format!("class:{}", new_class.id), None, serde_json::to_value(&new_class).ok());
+
+        Ok((new_class, entry))
+    })?;
+
+    Ok(new_class)
+}
+
+fn set_class_pilots(runtime: &Runtime, class_pilots_dto: ClassPilotsDto) -> Result<Class, ErrorMessage> {
+    let ClassPilotsDto { race_event_id, class_id, pilot_ids } = class_pilots_dto;
+    let mut db = Db::event(race_event_id)?;
+
+    let class = db.find_class(class_id)?.ok_or(ErrorMessage {
+        code: ErrorCode::NotFound,
+        message: format!("Class with id '{}' does not exist", class_id),
+    })?;
+
+    let pilots = db.find_pilots()?;
+    if let Some(pilot_id) = pilot_ids.iter().find(|pilot_id| !pilots.iter().any(|pilot| pilot.id == **pilot_id)) {
+        return Err(ErrorMessage {
+            code: ErrorCode::NotFound,
+            message: format!("Pilot with id '{}' does not exist", pilot_id),
+        });
+    }
+
+    let updated_class = db.audited(|db| -> Result<_, DbError> {
+        db.update_class_pilots(class_id, &pilot_ids)?;
+        let updated_class = db.find_class(class_id)?.unwrap_or(class.clone());
+        let entry = audit_entry(
+            runtime,
+            "class_pilots_changed",
+            None,
+            format!("class:{}", class_id),
+            serde_json::to_value(&class.pilot_ids).ok(),
+            serde_json::to_value(&updated_class.pilot_ids).ok(),
+        );
+
+        Ok((updated_class, entry))
+    })?;
+
+    Ok(updated_class)
+}
+
+fn add_round(runtime: &Runtime, new_round_dto: NewRoundDto) -> Result<Round, ErrorMessage> {
+    let NewRoundDto { race_event_id, class_id, name, round_type } = new_round_dto;
+
+    if name.is_empty() {
+        return Err(ErrorMessage {
+            code: ErrorCode::InvalidRequest,
+            message: "Missing 'name' property in Round".to_string(),
+        });
+    }
+
+    let mut db = Db::event(race_event_id)?;
+    if db.find_class(class_id)?.is_none() {
+        return Err(ErrorMessage {
+            code: ErrorCode::NotFound,
+            message: format!("Class with id '{}' does not exist", class_id),
+        });
+    }
+
+    let new_round = db.audited(|db| -> Result<_, DbError> {
+        let new_round = db.insert_round(class_id, name, round_type)?;
+        let entry = audit_entry(runtime, "round_added", None, format!("round:{}", new_round.id), None, serde_json::to_value(&new_round).ok());
+
+        Ok((new_round, entry))
+    })?;
+
+    Ok(new_round)
+}
+
+fn set_pilot_video_system(state: &mut State, runtime: &Runtime, pilot_video_system_dto: PilotVideoSystemDto) -> Result<Pilot, ErrorMessage> {
+    let PilotVideoSystemDto { race_event_id, pilot_id, video_system } = pilot_video_system_dto;
+    let mut db = Db::event(race_event_id)?;
+
+    let pilot = db.find_pilots()?.into_iter().find(|pilot| pilot.id == pilot_id).ok_or(ErrorMessage {
+        code: ErrorCode::NotFound,
+        message: format!("Pilot with id '{}' does not exist", pilot_id),
+    })?;
+
+    db.audited(|db| -> Result<_, DbError> {
+        db.update_pilot_video_system(pilot_id, video_system)?;
+        Ok(((), audit_entry(
+            runtime,
+            "pilot_video_system_changed",
+            None,
+            format!("pilot:{}", pilot_id),
+            serde_json::to_value(pilot.video_system).ok(),
+            serde_json::to_value(video_system).ok(),
+        )))
+    })?;
+
+    let pilot = Pilot { video_system, ..pilot };
+    if let Some(state_pilot) = state.pilots.iter_mut().find(|state_pilot| state_pilot.id == pilot_id) {
+        *state_pilot = pilot.clone();
+    }
+
+    Ok(pilot)
+}
+
+fn find_event_standings(race_filter_dto: RaceFilterDto) -> Result<EventStandingsDto, ErrorMessage> {
+    let RaceFilterDto { race_event_id, class_id, round_id } = race_filter_dto;
+    let db = Db::event(race_event_id)?;
+
+    let settings = db.find_points_settings()?;
+    let standings = standings::compute(&db.find_races(class_id, round_id)?, &settings);
+
+    Ok(EventStandingsDto { settings, standings })
+}
+
+fn set_points_settings(runtime: &Runtime, race_event_id: i64, settings: PointsSettings) -> Result<(), ErrorMessage> {
+    let mut db = Db::event(race_event_id)?;
+    let previous_settings = db.find_points_settings()?;
+
+    db.audited(|db| -> Result<_, DbError> {
+        db.update_points_settings(&settings)?;
+        Ok(((), audit_entry(
+            runtime,
+            "points_settings_changed",
+            None,
+            format!("race_event:{}", race_event_id),
+            serde_json::to_value(&previous_settings).ok(),
+            serde_json::to_value(&settings).ok(),
+        )))
+    })?;
+
+    Ok(())
+}
+
+async fn handle_action(state: &mut State, db: &Db, runtime: &mut Runtime, action: Actions) {
+    match action {
+        Actions::Init(invoke_request) => {
+            invoke_request.response_tx.send(Ok(state.clone())).unwrap_or(());
+        }
+        Actions::LoadRaceEvent(invoke_request) => {
+            let result = load_race_event(invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::CreateRaceEvent(invoke_request) => {
+            let result = create_race_event(state, db, runtime, invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::AddPilot(invoke_request) => {
+            let result = add_pilot(state, runtime, db, invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::AddPilotProfile(invoke_request) => {
+            let result = add_pilot_profile(db, invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::UpdatePilotProfile(invoke_request) => {
+            let result = update_pilot_profile(state, runtime, db, invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::FindPilotProfiles(invoke_request) => {
+            let result = db.find_pilot_profiles().map_err(ErrorMessage::from);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::ImportPilots(invoke_request) => {
+            let result = import_pilots(db, runtime, invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::AddRace(invoke_request) => {
+            let result = add_race(state, runtime, invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::GenerateHeats(invoke_request) => {
+            let result = generate_heats(state, runtime, invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::CreateBracket(invoke_request) => {
+            let result = create_bracket(state, runtime, invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::GetBrackets(invoke_request) => {
+            let result = Db::event(invoke_request.body)
+                .and_then(|db| db.find_brackets())
+                .map_err(ErrorMessage::from);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::AddClass(invoke_request) => {
+            let result = add_class(runtime, invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::SetClassPilots(invoke_request) => {
+            let result = set_class_pilots(runtime, invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::AddRound(invoke_request) => {
+            let result = add_round(runtime, invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::FindRaces(invoke_request) => {
+            let RaceFilterDto { race_event_id, class_id, round_id } = invoke_request.body;
+            let result = Db::event(race_event_id)
+                .and_then(|db| db.find_races(class_id, round_id))
+                .map_err(ErrorMessage::from);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::CheckChannels(invoke_request) => {
+            let issues = channels::check_lineup(&invoke_request.body, &InterferenceSettings::default());
+            invoke_request.response_tx.send(Ok(issues)).unwrap_or(());
+        }
+        Actions::SetPilotVideoSystem(invoke_request) => {
+            let result = set_pilot_video_system(state, runtime, invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::AssignChannels(invoke_request) => {
+            let result = reassign_channels(state, runtime, invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::AddLap(invoke_request) => {
+            let NewLapDto { race_event_id, race_id, heat_id, crossed_at } = invoke_request.body;
+            let result = correct_laps(state, runtime, race_event_id, race_id, LapCorrection::Add { heat_id, crossed_at });
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::DeleteLap(invoke_request) => {
+            let LapRefDto { race_event_id, race_id, lap_id } = invoke_request.body;
+            let result = correct_laps(state, runtime, race_event_id, race_id, LapCorrection::Delete { lap_id });
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::RestoreLap(invoke_request) => {
+            let LapRefDto { race_event_id, race_id, lap_id } = invoke_request.body;
+            let result = correct_laps(state, runtime, race_event_id, race_id, LapCorrection::Restore { lap_id });
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::SplitLap(invoke_request) => {
+            let SplitLapDto { race_event_id, race_id, lap_id, crossed_at } = invoke_request.body;
+            let result = correct_laps(state, runtime, race_event_id, race_id, LapCorrection::Split { lap_id, crossed_at });
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::MergeLaps(invoke_request) => {
+            let LapRefDto { race_event_id, race_id, lap_id } = invoke_request.body;
+            let result = correct_laps(state, runtime, race_event_id, race_id, LapCorrection::Merge { lap_id });
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::FindAuditLog(invoke_request) => {
+            let AuditLogFilterDto { race_event_id, race_id } = invoke_request.body;
+            let result = Db::event(race_event_id)
+                .and_then(|db| db.find_audit_log(race_id))
+                .map_err(ErrorMessage::from);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::GetOperator(invoke_request) => {
+            invoke_request.response_tx.send(Ok(runtime.operator.clone())).unwrap_or(());
+        }
+        Actions::SetOperator(invoke_request) => {
+            if invoke_request.body.trim().is_empty() {
+                invoke_request
+                    .response_tx
+                    .send(Err(ErrorMessage {
+                        code: ErrorCode::InvalidRequest,
+                        message: "Operator name can not be empty".to_string(),
+                    }))
+                    .unwrap_or(());
+            } else {
+                let operator = invoke_request.body.trim().to_string();
+                let result = db.update_operator(&operator)
+                    .map(|_| {
+                        runtime.operator = operator;
+                        runtime.operator.clone()
+                    })
+                    .map_err(ErrorMessage::from);
+                invoke_request.response_tx.send(result).unwrap_or(());
+            }
+        }
+        Actions::RemoveRaceEvent(invoke_request) => {
+            let result = remove_race_event(state, db, invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::StartRace(invoke_request) => {
+            start_race(state, runtime, invoke_request).await;
+        }
+        Actions::StopRace(invoke_request) => {
+            let result = transition_race(state, runtime, invoke_request.body, RaceTransition::Stop).await;
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::InterruptRace(invoke_request) => {
+            let result = transition_race(state, runtime, invoke_request.body, RaceTransition::Interrupt).await;
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::ResumeRace(invoke_request) => {
+            let result = transition_race(state, runtime, invoke_request.body, RaceTransition::Resume).await;
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::FinishRace(invoke_request) => {
+            let result = transition_race(state, runtime, invoke_request.body, RaceTransition::Finish).await;
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::SendCommand(invoke_request) => {
+            let result = send_command(&runtime.device_tx, invoke_request.body).await;
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::SetDetectionSettings(invoke_request) => {
+            let NodeDetectionSettingsDto { node, settings } = invoke_request.body;
+
+            if settings.exit_threshold > settings.enter_threshold {
+                invoke_request
+                    .response_tx
+                    .send(Err(ErrorMessage {
+                        code: ErrorCode::InvalidRequest,
+                        message: "Exit threshold can not be higher than enter threshold".to_string(),
+                    }))
+                    .unwrap_or(());
+            } else {
+                let result = audit_open_events(
+                    state,
+                    runtime,
+                    "detection_settings_changed",
+                    format!("node:{}", node),
+                    serde_json::to_value(runtime.lap_detector.settings_for(node)).ok(),
+                    serde_json::to_value(settings).ok(),
+                );
+                if result.is_ok() {
+                    runtime.lap_detector.set_settings(node, settings);
+                }
+                invoke_request.response_tx.send(result.map_err(ErrorMessage::from)).unwrap_or(());
+            }
+        }
+        Actions::SetStartSequenceSettings(invoke_request) => {
+            let settings = invoke_request.body;
+
+            if settings.min_delay > settings.max_delay {
+                invoke_request
+                    .response_tx
+                    .send(Err(ErrorMessage {
+                        code: ErrorCode::InvalidRequest,
+                        message: "Minimal start delay can not be longer than maximal start delay".to_string(),
+                    }))
+                    .unwrap_or(());
+            } else {
+                let result = audit_open_events(
+                    state,
+                    runtime,
+                    "start_sequence_settings_changed",
+                    "start_sequence".to_string(),
+                    serde_json::to_value(runtime.start_sequence_settings).ok(),
+                    serde_json::to_value(settings).ok(),
+                );
+                if result.is_ok() {
+                    runtime.start_sequence_settings = settings;
+                }
+                invoke_request.response_tx.send(result.map_err(ErrorMessage::from)).unwrap_or(());
+            }
+        }
+        Actions::ListDevices(invoke_request) => {
+            invoke_request.response_tx.send(Ok(get_available_devices())).unwrap_or(());
+        }
+        Actions::ConnectDevice(invoke_request) => {
+            let selection = invoke_request.body.clone();
+            let result = control_device(&runtime.device_control_tx, |response_tx| {
+                DeviceControl::Connect(invoke_request.body, response_tx)
+            }).await.and_then(|result| result.map_err(ErrorMessage::from));
+
+            if result.is_ok() {
+                if let Err(error) = db.update_device_selection(Some(&selection)) {
+                    println!("Can not store the device selection: {}", error);
+                }
+            }
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::DisconnectDevice(invoke_request) => {
+            let result = control_device(&runtime.device_control_tx, DeviceControl::Disconnect).await;
+
+            if result.is_ok() {
+                if let Err(error) = db.update_device_selection(None) {
+                    println!("Can not store the device selection: {}", error);
+                }
+            }
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::GetLeaderboard(invoke_request) => {
+            let RaceRefDto { race_event_id, race_id } = invoke_request.body;
+            let result = Db::event(race_event_id)
+                .and_then(|db| db.find_race_with_heats(race_id))
+                .map_err(ErrorMessage::from)
+                .and_then(|race| race.ok_or(ErrorMessage {
+                    code: ErrorCode::NotFound,
+                    message: format!("Race with id '{}' does not exist", race_id),
+                }))
+                .map(|race| leaderboard::compute(&race.heats));
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::GetEventStandings(invoke_request) => {
+            let result = find_event_standings(invoke_request.body);
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
+        Actions::SetPointsSettings(invoke_request) => {
+            let PointsSettingsDto { race_event_id, settings } = invoke_request.body;
+
+            if settings.tie_breaks.contains(&TieBreak::BestConsecutive { laps: 0 }) {
+                invoke_request
+                    .response_tx
+                    .send(Err(ErrorMessage {
+                        code: ErrorCode::InvalidRequest,
+                        message: "Consecutive laps tie-break needs at least one lap".to_string(),
+                    }))
+                    .unwrap_or(());
+            } else {
+                let result = set_points_settings(runtime, race_event_id, settings);
+                invoke_request.response_tx.send(result).unwrap_or(());
+            }
+        }
+        Actions::GetConsecutiveRanking(invoke_request) => {
+            let ConsecutiveRankingDto { race_event_id, laps, class_id, round_id } = invoke_request.body;
+
+            if laps == 0 {
+                invoke_request
+                    .response_tx
+                    .send(Err(ErrorMessage {
+                        code: ErrorCode::InvalidRequest,
+                        message: "Consecutive laps ranking needs at least one lap".to_string(),
+                    }))
+                    .unwrap_or(());
+            } else {
+                let result = Db::event(race_event_id)
+                    .and_then(|db| db.find_races(class_id, round_id))
+                    .map(|races| standings::compute_consecutive(&races, laps))
+                    .map_err(ErrorMessage::from);
+                invoke_request.response_tx.send(result).unwrap_or(());
+            }
+        }
+        Actions::GetDeviceStatus(invoke_request) => {
+            let result = control_device(&runtime.device_control_tx, DeviceControl::Status).await;
+            invoke_request.response_tx.send(result).unwrap_or(());
+        }
     }
 }
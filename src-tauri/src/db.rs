@@ -1,146 +1,847 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
 use chrono::{DateTime, Utc};
-use rusqlite::{Connection, params, Result};
-use crate::core::{Heat, NewRaceDto, Pilot, Race, RaceEvent, RaceEventType, RaceStatus};
+use rusqlite::{Connection, OptionalExtension, params};
+use crate::bracket::{Bracket, BracketRace, BracketType, NewBracketDto, PlannedRace};
+use crate::channels::{Channel, VideoSystem};
+use crate::core::{Class, Heat, Lap, LapSource, AuditEntry, NewAuditEntry, NewHeatDto, NewPilotProfileDto, NewRaceDto, Pilot, PilotProfile, Race, RaceEvent, RaceEventType, RaceStatus, Round, RoundType};
+use crate::device::DeviceSelection;
+use crate::migrations;
+use crate::migrations::{Migration, EVENT_MIGRATIONS, MAIN_MIGRATIONS};
+use crate::standings::PointsSettings;
+use crate::storage;
+
+#[derive(Debug)]
+pub enum DbError {
+    Busy,
+    NewerVersion { found: u32, supported: u32 },
+    Invalid(String),
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Busy => write!(f, "Database is busy, try again"),
+            DbError::NewerVersion { found, supported } => {
+                write!(f, "Database schema version {} is newer than the supported version {}, update the app to open it", found, supported)
+            }
+            DbError::Invalid(message) => write!(f, "{}", message),
+            DbError::Sqlite(error) => write!(f, "Database error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl From<rusqlite::Error> for DbError {
+    fn from(error: rusqlite::Error) -> Self {
+        match error.sqlite_error_code() {
+            Some(rusqlite::ErrorCode::DatabaseBusy) | Some(rusqlite::ErrorCode::DatabaseLocked) => DbError::Busy,
+            _ => DbError::Sqlite(error),
+        }
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> rusqlite::Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
 
 pub struct Db {
     connection: Connection,
-    name: String,
+}
+
+fn parse_channels(bracket_id: i64, channels: &str) -> Result<Vec<Channel>, DbError> {
+    serde_json::from_str(channels)
+        .map_err(|e| DbError::Invalid(format!("Channels of bracket {} are invalid: {}", bracket_id, e)))
+}
+
+fn log_migrations(path: &Path, applied: &[&Migration]) {
+    for migration in applied {
+        println!("Migrated {} to version {}: {}", path.display(), migration.version, migration.description);
+    }
 }
 
 impl Db {
-    pub fn new(name: String) -> Db {
-        let connection = Connection::open(&name).expect("Can not open the database!");
+    fn open(path: PathBuf, migrations: &[Migration]) -> Result<Db, DbError> {
+        Db::open_migrated(path, migrations).map(|(db, _)| db)
+    }
 
-        Db {
-            connection,
-            name
-        }
+    fn open_migrated<'a>(path: PathBuf, migrations: &'a [Migration]) -> Result<(Db, Vec<&'a Migration>), DbError> {
+        let mut connection = Connection::open(&path)?;
+        let applied = migrations::migrate(&mut connection, migrations)?;
+
+        Ok((Db { connection }, applied))
     }
 
+    pub fn main() -> Result<Db, DbError> {
+        Db::open(storage::main_db_path(), MAIN_MIGRATIONS)
+    }
 
-    pub fn init() -> Vec<RaceEvent> {
-        let connection = Connection::open("db").expect("Can not open the database!");
+    pub fn event(race_event_id: i64) -> Result<Db, DbError> {
+        Db::open(storage::event_db_path(race_event_id), EVENT_MIGRATIONS)
+    }
 
-        connection.execute("CREATE TABLE IF NOT EXISTS raceEvents (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL,
-            created_at TEXT NOT NULL,
-            race_event_type TEXT NOT NULL
-        )", ()).expect("Can not create the race events table!");
+    // Every file is migrated once at startup, so applied migrations are logged only here. An event that can
+    // not be migrated is reported again when it is opened.
+    pub fn init() -> Result<Vec<RaceEvent>, DbError> {
+        let main_path = storage::main_db_path();
+        let (db, applied) = Db::open_migrated(main_path.clone(), MAIN_MIGRATIONS)?;
+        log_migrations(&main_path, &applied);
+        let connection = db.connection;
+
+        let mut statement = connection.prepare("SELECT id FROM raceEvents")?;
+        let race_event_ids = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+        for race_event_id in race_event_ids {
+            let path = storage::event_db_path(race_event_id);
+            match Db::open_migrated(path.clone(), EVENT_MIGRATIONS) {
+                Ok((_, applied)) => log_migrations(&path, &applied),
+                Err(error) => println!("Can not migrate {}: {}", path.display(), error),
+            }
+        }
 
-        let mut statement = connection.prepare("SELECT id, race_event_type, created_at, name FROM raceEvents").expect("Can not prepare the statement!");
+        let mut statement = connection.prepare("SELECT id, race_event_type, created_at, name FROM raceEvents")?;
 
-        let race_events_iter = statement.query_map([], |row| {
+        let race_events = statement.query_map([], |row| {
             Ok(RaceEvent::new(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
-        }).unwrap();
+        })?.collect::<rusqlite::Result<_>>()?;
 
-        race_events_iter.map(|r| r.unwrap()).collect()
+        Ok(race_events)
     }
 
-    pub fn insert_race(&self, name: String, created_at: DateTime<Utc>, race_event_type: RaceEventType) -> RaceEvent {
+    pub fn insert_race(&self, name: String, created_at: DateTime<Utc>, race_event_type: RaceEventType) -> Result<RaceEvent, DbError> {
         self.connection.execute(
             "INSERT INTO raceEvents (name, created_at, race_event_type) VALUES (?1, ?2, ?3)",
             params![name, created_at, race_event_type.to_string()]
-        ).unwrap();
+        )?;
 
-        let mut connection = Connection::open(format!("{}", self.connection.last_insert_rowid())).expect("Can not open the race event database during first connection");
+        let race_event_id = self.connection.last_insert_rowid();
 
-        let tx = connection.transaction().unwrap();
+        if let Err(error) = Db::event(race_event_id) {
+            self.connection.execute("DELETE FROM raceEvents WHERE id = ?1", params![race_event_id])?;
+            return Err(error);
+        }
 
-        tx.execute("CREATE TABLE IF NOT EXISTS pilots (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL
-        )", ()).expect("Can not create the pilots table!");
+        Ok(RaceEvent::new(race_event_id, race_event_type, created_at, name))
+    }
 
-        tx.execute("CREATE TABLE IF NOT EXISTS races (
-            id INTEGER PRIMARY KEY,
-            name INTEGER NOT NULL,
-            status TEXT NOT NULL
-        )", ()).expect("Can not create the races table!");
+    pub fn remove_race_event(&self, race_event_id: i64) -> Result<(), DbError> {
+        storage::remove_database(&storage::event_db_path(race_event_id));
+        self.connection.execute("DELETE FROM raceEvents WHERE id = ?1", params![race_event_id])?;
 
-        tx.execute("CREATE TABLE IF NOT EXISTS heats (
-            id INTEGER PRIMARY KEY,
-            no INTEGER NOT NULL,
-            channel TEXT NOT NULL,
-            pilot_id INTEGER NOT NULL,
-            race_id INTEGER NOT NULL,
-            rssi_raw TEXT NOT NULL,
-            FOREIGN KEY(pilot_id) REFERENCES pilots(id),
-            FOREIGN KEY(race_id) REFERENCES races(id)
-        )", ()).expect("Can not create the heats table!");
+        Ok(())
+    }
 
-        tx.commit().expect("Can not perform transaction!");
+    pub fn find_device_selection(&self) -> Result<Option<DeviceSelection>, DbError> {
+        let device_selection = self.connection.query_row(
+            "SELECT value FROM settings WHERE key = 'device_selection'",
+            [],
+            |row| row.get(0)
+        ).optional()?;
 
-        RaceEvent::new(self.connection.last_insert_rowid(), race_event_type, created_at, name)
+        Ok(device_selection)
     }
 
-    pub fn remove_race_event(&self, race_event_id: i64) {
-        let connection = Connection::open(format!("{}", self.connection.last_insert_rowid())).expect("Can not open the race event database");
-        std::fs::remove_file(format!("{}", race_event_id)).unwrap_or(());
-        self.connection
-            .execute("DELETE FROM raceEvents WHERE id = ?1", params![race_event_id]).expect("Can not remove raceEvent!");
+    pub fn update_device_selection(&self, device_selection: Option<&DeviceSelection>) -> Result<(), DbError> {
+        match device_selection {
+            Some(device_selection) => self.connection.execute(
+                "INSERT OR REPLACE INTO settings (key, value) VALUES ('device_selection', ?1)",
+                params![device_selection]
+            ),
+            None => self.connection.execute("DELETE FROM settings WHERE key = 'device_selection'", ()),
+        }?;
+
+        Ok(())
     }
 
-    pub fn insert_pilot(&self, name: String) -> Pilot {
+    pub fn find_operator(&self) -> Result<Option<String>, DbError> {
+        let operator = self.connection.query_row(
+            "SELECT value FROM settings WHERE key = 'operator'",
+            [],
+            |row| row.get(0)
+        ).optional()?;
+
+        Ok(operator)
+    }
+
+    pub fn update_operator(&self, operator: &str) -> Result<(), DbError> {
         self.connection.execute(
-            "INSERT INTO pilots (name) VALUES (?1)",
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('operator', ?1)",
+            params![operator]
+        )?;
+
+        Ok(())
+    }
+
+    pub fn insert_class(&self, name: String) -> Result<Class, DbError> {
+        self.connection.execute(
+            "INSERT INTO classes (name) VALUES (?1)",
             params![name]
-        ).unwrap();
+        )?;
+
+        Ok(Class::new(self.connection.last_insert_rowid(), name, Vec::new(), Vec::new()))
+    }
+
+    pub fn find_classes(&self) -> Result<Vec<Class>, DbError> {
+        let mut statement = self.connection.prepare("SELECT id, name FROM classes")?;
+
+        let classes = statement.query_map([], |row| self.map_class(row))?.collect::<rusqlite::Result<_>>()?;
+
+        Ok(classes)
+    }
+
+    pub fn find_class(&self, class_id: i64) -> Result<Option<Class>, DbError> {
+        let class = self.connection.query_row(
+            "SELECT id, name FROM classes WHERE id = ?1",
+            [class_id],
+            |row| self.map_class(row)
+        ).optional()?;
+
+        Ok(class)
+    }
+
+    fn map_class(&self, row: &rusqlite::Row) -> rusqlite::Result<Class> {
+        let class_id: i64 = row.get(0)?;
+
+        let mut pilots_statement = self.connection.prepare(
+            "SELECT pilot_id FROM class_pilots WHERE class_id = ?1 ORDER BY pilot_id"
+        )?;
+
+        let pilot_ids = pilots_statement.query_map([class_id], |pilot_row| pilot_row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let mut rounds_statement = self.connection.prepare(
+            "SELECT id, class_id, no, name, round_type FROM rounds WHERE class_id = ?1 ORDER BY no"
+        )?;
+
+        let rounds = rounds_statement.query_map([class_id], map_round)?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(Class::new(class_id, row.get(1)?, pilot_ids, rounds))
+    }
+
+    pub fn update_class_pilots(&mut self, class_id: i64, pilot_ids: &[i64]) -> Result<(), DbError> {
+        let tx = self.connection.savepoint()?;
+
+        tx.execute("DELETE FROM class_pilots WHERE class_id = ?1", [class_id])?;
+
+        for pilot_id in pilot_ids {
+            tx.execute(
+                "INSERT OR IGNORE INTO class_pilots (class_id, pilot_id) VALUES (?1, ?2)",
+                params![class_id, pilot_id]
+            )?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    pub fn find_class_pilots(&self, class_id: i64) -> Result<Vec<Pilot>, DbError> {
+        let mut statement = self.connection.prepare(
+            "SELECT p.id, p.name, p.video_system, p.profile_id, p.preferred_channel FROM pilots p JOIN class_pilots c ON c.pilot_id = p.id WHERE c.class_id = ?1 ORDER BY p.id"
+        )?;
+
+        let pilots = statement.query_map([class_id], map_pilot)?.collect::<rusqlite::Result<_>>()?;
+
+        Ok(pilots)
+    }
+
+    pub fn insert_round(&self, class_id: i64, name: String, round_type: RoundType) -> Result<Round, DbError> {
+        let no: u32 = self.connection.query_row(
+            "SELECT COUNT(*) + 1 FROM rounds WHERE class_id = ?1",
+            [class_id],
+            |row| row.get(0)
+        )?;
+
+        self.connection.execute(
+            "INSERT INTO rounds (class_id, no, name, round_type) VALUES (?1, ?2, ?3, ?4)",
+            params![class_id, no, name, round_type.to_string()]
+        )?;
+
+        Ok(Round::new(self.connection.last_insert_rowid(), class_id, no, name, round_type))
+    }
+
+    pub fn find_round(&self, round_id: i64) -> Result<Option<Round>, DbError> {
+        let round = self.connection.query_row(
+            "SELECT id, class_id, no, name, round_type FROM rounds WHERE id = ?1",
+            [round_id],
+            map_round
+        ).optional()?;
+
+        Ok(round)
+    }
+
+    pub fn find_points_settings(&self) -> Result<PointsSettings, DbError> {
+        let points_settings = self.connection.query_row(
+            "SELECT value FROM settings WHERE key = 'points_settings'",
+            [],
+            |row| row.get(0)
+        ).optional()?;
+
+        Ok(points_settings.unwrap_or_default())
+    }
+
+    pub fn update_points_settings(&self, points_settings: &PointsSettings) -> Result<(), DbError> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('points_settings', ?1)",
+            params![points_settings]
+        )?;
+
+        Ok(())
+    }
+
+    pub fn insert_pilot(&self, profile: &PilotProfile) -> Result<Pilot, DbError> {
+        self.connection.execute(
+            "INSERT INTO pilots (name, video_system, profile_id, preferred_channel) VALUES (?1, ?2, ?3, ?4)",
+            params![profile.callsign, profile.video_system.to_string(), profile.id, profile.preferred_channel]
+        )?;
+
+        Ok(Pilot::new(self.connection.last_insert_rowid(), profile.callsign.clone(), profile.video_system, Some(profile.id), profile.preferred_channel))
+    }
+
+    pub fn link_pilot_profile(&self, pilot_id: i64, profile: &PilotProfile) -> Result<(), DbError> {
+        self.connection.execute(
+            "UPDATE pilots SET name = ?1, video_system = ?2, profile_id = ?3, preferred_channel = ?4 WHERE id = ?5",
+            params![profile.callsign, profile.video_system.to_string(), profile.id, profile.preferred_channel, pilot_id]
+        )?;
+
+        Ok(())
+    }
+
+    // Event rosters keep a copy of the profile, so an event database is still complete on its own.
+    // An event is finished once it has races and all of them have been flown.
+    pub fn is_finished(&self) -> Result<bool, DbError> {
+        let (races, unfinished): (i64, i64) = self.connection.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(status != ?1), 0) FROM races",
+            [RaceStatus::Finished.to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?))
+        )?;
+
+        Ok(races > 0 && unfinished == 0)
+    }
+
+    pub fn sync_pilot_profile(&self, profile: &PilotProfile) -> Result<(), DbError> {
+        self.connection.execute(
+            "UPDATE pilots SET name = ?1, video_system = ?2, preferred_channel = ?3 WHERE profile_id = ?4",
+            params![profile.callsign, profile.video_system.to_string(), profile.preferred_channel, profile.id]
+        )?;
+
+        Ok(())
+    }
+
+    pub fn insert_pilot_profile(&self, new_pilot_profile_dto: &NewPilotProfileDto) -> Result<PilotProfile, DbError> {
+        let NewPilotProfileDto { callsign, real_name, country, team, video_system, preferred_channel, transponder, phonetic_name, avatar_path } = new_pilot_profile_dto;
+
+        self.connection.execute(
+            "INSERT INTO pilot_profiles (callsign, real_name, country, team, video_system, preferred_channel, transponder, phonetic_name, avatar_path)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![callsign, real_name, country, team, video_system.to_string(), preferred_channel, transponder, phonetic_name, avatar_path]
+        )?;
+
+        let profile = self.connection.query_row(
+            &format!("SELECT {} FROM pilot_profiles WHERE id = ?1", PILOT_PROFILE_COLUMNS),
+            [self.connection.last_insert_rowid()],
+            map_pilot_profile
+        )?;
+
+        Ok(profile)
+    }
+
+    pub fn update_pilot_profile(&self, profile: &PilotProfile) -> Result<(), DbError> {
+        self.connection.execute(
+            "UPDATE pilot_profiles SET callsign = ?1, real_name = ?2, country = ?3, team = ?4, video_system = ?5,
+            preferred_channel = ?6, transponder = ?7, phonetic_name = ?8, avatar_path = ?9 WHERE id = ?10",
+            params![
+                profile.callsign, profile.real_name, profile.country, profile.team, profile.video_system.to_string(),
+                profile.preferred_channel, profile.transponder, profile.phonetic_name, profile.avatar_path, profile.id
+            ]
+        )?;
+
+        Ok(())
+    }
+
+    pub fn find_pilot_profiles(&self) -> Result<Vec<PilotProfile>, DbError> {
+        let mut statement = self.connection.prepare(
+            &format!("SELECT {} FROM pilot_profiles ORDER BY callsign", PILOT_PROFILE_COLUMNS)
+        )?;
+
+        let profiles = statement.query_map([], map_pilot_profile)?.collect::<rusqlite::Result<_>>()?;
+
+        Ok(profiles)
+    }
+
+    pub fn find_pilot_profile(&self, profile_id: i64) -> Result<Option<PilotProfile>, DbError> {
+        let profile = self.connection.query_row(
+            &format!("SELECT {} FROM pilot_profiles WHERE id = ?1", PILOT_PROFILE_COLUMNS),
+            [profile_id],
+            map_pilot_profile
+        ).optional()?;
+
+        Ok(profile)
+    }
+
+    pub fn find_pilot_profile_by_callsign(&self, callsign: &str) -> Result<Option<PilotProfile>, DbError> {
+        let profile = self.connection.query_row(
+            &format!("SELECT {} FROM pilot_profiles WHERE callsign = ?1", PILOT_PROFILE_COLUMNS),
+            [callsign],
+            map_pilot_profile
+        ).optional()?;
+
+        Ok(profile)
+    }
 
-        Pilot {name, id: self.connection.last_insert_rowid()}
+    pub fn update_pilot_video_system(&self, pilot_id: i64, video_system: VideoSystem) -> Result<(), DbError> {
+        self.connection.execute(
+            "UPDATE pilots SET video_system = ?1 WHERE id = ?2",
+            params![video_system.to_string(), pilot_id]
+        )?;
+
+        Ok(())
     }
 
-    pub fn find_pilots(&self) -> Vec<Pilot> {
-        let mut statement = self.connection.prepare("SELECT id, name FROM pilots").expect("Can not prepare the statement!");
+    pub fn update_heat_channel(&self, heat_id: i64, channel: Channel) -> Result<(), DbError> {
+        self.connection.execute(
+            "UPDATE heats SET channel = ?1 WHERE id = ?2",
+            params![channel, heat_id]
+        )?;
+
+        Ok(())
+    }
+
+    pub fn find_pilots(&self) -> Result<Vec<Pilot>, DbError> {
+        let mut statement = self.connection.prepare("SELECT id, name, video_system, profile_id, preferred_channel FROM pilots")?;
+
+        let pilots = statement.query_map([], map_pilot)?.collect::<rusqlite::Result<_>>()?;
 
-        let pilots_iter = statement.query_map([], |row| {
-            Ok(Pilot::new(row.get(0)?, row.get(1)?))
-        }).unwrap();
+        Ok(pilots)
+    }
+
+    pub fn insert_race_with_heats(&mut self, new_race_dto: NewRaceDto) -> Result<Race, DbError> {
+        let tx = self.connection.savepoint()?;
+        let race = insert_race_with_heats(&tx, new_race_dto)?;
+        tx.commit()?;
 
-        pilots_iter.map(|r| r.unwrap()).collect()
+        Ok(race)
     }
 
-    pub fn insert_race_with_heats(&mut self, new_race_dto: NewRaceDto) -> Race {
-        let tx = self.connection.transaction().unwrap();
+    pub fn insert_bracket(&mut self, new_bracket_dto: &NewBracketDto, planned_races: &[PlannedRace]) -> Result<Bracket, DbError> {
+        let tx = self.connection.savepoint()?;
 
         tx.execute(
-            "INSERT INTO races (name, status) VALUES (?1, ?2)",
-            params![new_race_dto.name, RaceStatus::New.to_string()]
-        ).unwrap();
+            "INSERT INTO brackets (name, bracket_type, channels) VALUES (?1, ?2, ?3)",
+            params![new_bracket_dto.name, new_bracket_dto.bracket_type.to_string(), to_json(&new_bracket_dto.channels)?]
+        )?;
+
+        let bracket_id = tx.last_insert_rowid();
+        let mut race_ids: Vec<i64> = Vec::with_capacity(planned_races.len());
+        let mut bracket_races = Vec::with_capacity(planned_races.len());
+
+        for planned_race in planned_races {
+            let heats = planned_race.pilot_ids.iter().enumerate().map(|(index, pilot_id)| NewHeatDto {
+                no: index as u8 + 1,
+                pilot_id: *pilot_id,
+                channel: new_bracket_dto.channels.get(index).copied(),
+            }).collect();
+
+            let race = insert_race_with_heats(&tx, NewRaceDto {
+                name: format!("{} - {}", new_bracket_dto.name, planned_race.name()),
+                heats,
+                race_event_id: new_bracket_dto.race_event_id,
+                format: new_bracket_dto.format.clone(),
+                round_id: new_bracket_dto.round_id,
+            })?;
+
+            tx.execute(
+                "INSERT INTO bracket_races (race_id, bracket_id, side, round, position) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![race.id, bracket_id, planned_race.side.to_string(), planned_race.round, planned_race.position]
+            )?;
+
+            for feed in &planned_race.feeds {
+                tx.execute(
+                    "INSERT INTO bracket_feeds (race_id, slot, source_race_id, source_place) VALUES (?1, ?2, ?3, ?4)",
+                    params![race.id, feed.slot, race_ids[feed.source], feed.place]
+                )?;
+            }
+
+            race_ids.push(race.id);
+            bracket_races.push(BracketRace {
+                race_id: race.id,
+                side: planned_race.side,
+                round: planned_race.round,
+                position: planned_race.position,
+            });
+        }
+
+        tx.commit()?;
+
+        Ok(Bracket::new(bracket_id, new_bracket_dto.name.clone(), new_bracket_dto.bracket_type, new_bracket_dto.channels.clone(), bracket_races))
+    }
+
+    pub fn find_brackets(&self) -> Result<Vec<Bracket>, DbError> {
+        let mut statement = self.connection.prepare(
+            "SELECT id, name, bracket_type, channels FROM brackets"
+        )?;
+
+        let rows: Vec<(i64, String, BracketType, String)> = statement.query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?.collect::<rusqlite::Result<_>>()?;
+
+        let mut races_statement = self.connection.prepare(
+            "SELECT race_id, side, round, position FROM bracket_races WHERE bracket_id = ?1 ORDER BY race_id"
+        )?;
+
+        let mut brackets = Vec::with_capacity(rows.len());
+        for (bracket_id, name, bracket_type, channels) in rows {
+            let races = races_statement.query_map([bracket_id], |race_row| {
+                Ok(BracketRace {
+                    race_id: race_row.get(0)?,
+                    side: race_row.get(1)?,
+                    round: race_row.get(2)?,
+                    position: race_row.get(3)?,
+                })
+            })?.collect::<rusqlite::Result<_>>()?;
+
+            brackets.push(Bracket::new(bracket_id, name, bracket_type, parse_channels(bracket_id, &channels)?, races));
+        }
+
+        Ok(brackets)
+    }
+
+    // Bracket races still to be flown that get pilots from the given race.
+    pub fn find_fed_race_ids(&self, race_id: i64) -> Result<Vec<i64>, DbError> {
+        let mut statement = self.connection.prepare(
+            "SELECT DISTINCT f.race_id FROM bracket_feeds f
+             JOIN races t ON t.id = f.race_id
+             WHERE f.source_race_id = ?1 AND t.status = ?2
+             ORDER BY f.race_id"
+        )?;
 
-        let new_race_id = tx.last_insert_rowid();
+        let race_ids = statement.query_map(params![race_id, RaceStatus::New.to_string()], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(race_ids)
+    }
+
+    // Puts the pilots finishing a bracket race into the races they advance to, returns the ids of those races.
+    pub fn advance_bracket(&mut self, race_id: i64, places: &[(u32, i64)]) -> Result<Vec<i64>, DbError> {
+        let tx = self.connection.savepoint()?;
+
+        let feeds: Vec<(i64, u8, u32, i64, String)> = {
+            let mut statement = tx.prepare(
+                "SELECT f.race_id, f.slot, f.source_place, b.id, b.channels FROM bracket_feeds f
+                 JOIN bracket_races r ON r.race_id = f.race_id
+                 JOIN brackets b ON b.id = r.bracket_id
+                 JOIN races t ON t.id = f.race_id
+                 WHERE f.source_race_id = ?1 AND t.status = ?2"
+            )?;
+
+            let feeds = statement.query_map(params![race_id, RaceStatus::New.to_string()], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+            })?.collect::<rusqlite::Result<_>>()?;
+
+            feeds
+        };
+
+        let mut advanced_race_ids = Vec::new();
+
+        for (target_race_id, slot, place, bracket_id, channels) in feeds {
+            let pilot_id = match places.iter().find(|(position, _)| *position == place) {
+                Some((_, pilot_id)) => *pilot_id,
+                None => continue,
+            };
+            let channels = parse_channels(bracket_id, &channels)?;
+            let channel = match channels.get(slot as usize - 1) {
+                Some(channel) => *channel,
+                None => continue,
+            };
+
+            tx.execute(
+                "DELETE FROM heats WHERE race_id = ?1 AND no = ?2",
+                params![target_race_id, slot]
+            )?;
 
-        let heats = new_race_dto.heats.iter().map(|heat| {
             tx.execute(
                 "INSERT INTO heats (no, channel, pilot_id, race_id, rssi_raw) VALUES (?1, ?2, ?3, ?4, ?5)",
-                params![heat.no, heat.channel, heat.pilot_id, new_race_id, ""]
-            ).unwrap();
+                params![slot, channel, pilot_id, target_race_id, ""]
+            )?;
 
-            Heat::new(tx.last_insert_rowid(), heat.no, heat.channel.clone(), heat.pilot_id)
-        }).collect();
+            if !advanced_race_ids.contains(&target_race_id) {
+                advanced_race_ids.push(target_race_id);
+            }
+        }
+
+        tx.commit()?;
+
+        Ok(advanced_race_ids)
+    }
 
-        tx.commit().unwrap();
+    pub fn update_heat_rssi_raw(&self, heat_id: i64, rssi_raw: String) -> Result<(), DbError> {
+        self.connection.execute(
+            "UPDATE heats SET rssi_raw = ?1 WHERE id = ?2",
+            params![rssi_raw, heat_id]
+        )?;
+
+        Ok(())
+    }
 
-        Race::new(new_race_id, new_race_dto.name, RaceStatus::New, heats)
+    pub fn find_races_with_heats(&self) -> Result<Vec<Race>, DbError> {
+        self.find_races(None, None)
     }
 
-    pub fn find_races_with_heats(&self) -> Vec<Race> {
+    pub fn find_races(&self, class_id: Option<i64>, round_id: Option<i64>) -> Result<Vec<Race>, DbError> {
         let mut races_statement = self.connection.prepare(
-            "SELECT id, name, status FROM races"
-        ).unwrap();
+            "SELECT r.id, r.name, r.status, r.format, r.started_at, r.finished_at, r.round_id FROM races r
+             LEFT JOIN rounds o ON o.id = r.round_id
+             WHERE (?1 IS NULL OR o.class_id = ?1) AND (?2 IS NULL OR r.round_id = ?2)"
+        )?;
 
-        races_statement.query_map([], |row| {
-            let race_id: i64 = row.get(0)?;
+        let races = races_statement.query_map(params![class_id, round_id], |row| self.map_race(row))?
+            .collect::<rusqlite::Result<_>>()?;
 
-            let mut heats_statement = self.connection.prepare(
-                "SELECT id, no, channel, pilot_id FROM heats WHERE race_id = ?1"
-            ).unwrap();
+        Ok(races)
+    }
 
-            let heats_iter = heats_statement.query_map([race_id], |heat_row| {
-                Ok(Heat::new(heat_row.get(0)?, heat_row.get(1)?, heat_row.get(2)?, heat_row.get(3)?))
-            }).unwrap().map(|heat| heat.unwrap()).collect();
+    pub fn find_race_with_heats(&self, race_id: i64) -> Result<Option<Race>, DbError> {
+        let race = self.connection.query_row(
+            "SELECT id, name, status, format, started_at, finished_at, round_id FROM races WHERE id = ?1",
+            [race_id],
+            |row| self.map_race(row)
+        ).optional()?;
 
-            Ok(Race::new(race_id, row.get(1)?, row.get(2)?, heats_iter))
-        }).unwrap().map(|race| race.unwrap()).collect()
+        Ok(race)
     }
+
+    pub fn update_race_status(&self, race_id: i64, status: RaceStatus, started_at: Option<DateTime<Utc>>, finished_at: Option<DateTime<Utc>>) -> Result<(), DbError> {
+        self.connection.execute(
+            "UPDATE races SET status = ?1, started_at = ?2, finished_at = ?3 WHERE id = ?4",
+            params![status.to_string(), started_at, finished_at, race_id]
+        )?;
+
+        Ok(())
+    }
+
+    fn map_race(&self, row: &rusqlite::Row) -> rusqlite::Result<Race> {
+        let race_id: i64 = row.get(0)?;
+
+        let mut heats_statement = self.connection.prepare(
+            "SELECT id, no, channel, pilot_id FROM heats WHERE race_id = ?1"
+        )?;
+
+        let heats = heats_statement.query_map([race_id], |heat_row| {
+            let heat_id: i64 = heat_row.get(0)?;
+            Ok(Heat::new(heat_id, heat_row.get(1)?, heat_row.get(2)?, heat_row.get(3)?, find_laps(&self.connection, heat_id)?))
+        })?.collect::<rusqlite::Result<_>>()?;
+
+        Ok(Race::new(race_id, row.get(1)?, row.get(2)?, heats, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?))
+    }
+
+    pub fn insert_lap(&mut self, heat_id: i64, crossed_at: i64, source: LapSource) -> Result<Lap, DbError> {
+        let tx = self.connection.savepoint()?;
+
+        tx.execute(
+            "INSERT INTO laps (heat_id, no, crossed_at, lap_time, source, deleted) VALUES (?1, 0, ?2, 0, ?3, 0)",
+            params![heat_id, crossed_at, source.to_string()]
+        )?;
+
+        let lap_id = tx.last_insert_rowid();
+        renumber_laps(&tx, heat_id)?;
+        let lap = find_lap(&tx, lap_id)?;
+
+        tx.commit()?;
+
+        Ok(lap)
+    }
+
+    pub fn find_laps(&self, heat_id: i64) -> Result<Vec<Lap>, DbError> {
+        Ok(find_laps(&self.connection, heat_id)?)
+    }
+
+    pub fn find_lap(&self, lap_id: i64) -> Result<Option<Lap>, DbError> {
+        Ok(find_lap(&self.connection, lap_id).optional()?)
+    }
+
+    pub fn find_audit_log(&self, race_id: Option<i64>) -> Result<Vec<AuditEntry>, DbError> {
+        let mut statement = self.connection.prepare(
+            "SELECT id, created_at, actor, action, race_id, subject, before, after FROM audit_log
+            WHERE ?1 IS NULL OR race_id = ?1 ORDER BY id DESC"
+        )?;
+
+        let entries = statement.query_map([race_id], |row| {
+            let before: Option<String> = row.get(6)?;
+            let after: Option<String> = row.get(7)?;
+
+            Ok(AuditEntry {
+                id: row.get(0)?,
+                created_at: row.get(1)?,
+                actor: row.get(2)?,
+                action: row.get(3)?,
+                race_id: row.get(4)?,
+                subject: row.get(5)?,
+                before: before.and_then(|before| serde_json::from_str(&before).ok()),
+                after: after.and_then(|after| serde_json::from_str(&after).ok()),
+            })
+        })?.collect::<rusqlite::Result<_>>()?;
+
+        Ok(entries)
+    }
+
+    pub fn insert_audit_entry(&self, new_audit_entry: &NewAuditEntry) -> Result<(), DbError> {
+        self.connection.execute(
+            "INSERT INTO audit_log (created_at, actor, action, race_id, subject, before, after) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                Utc::now(),
+                new_audit_entry.actor,
+                new_audit_entry.action,
+                new_audit_entry.race_id,
+                new_audit_entry.subject,
+                new_audit_entry.before.as_ref().map(|before| before.to_string()),
+                new_audit_entry.after.as_ref().map(|after| after.to_string())
+            ]
+        )?;
+
+        Ok(())
+    }
+
+    // Runs a change together with its audit entry in one transaction, neither is kept without the other.
+    // Mutations use savepoints, so they nest inside it.
+    pub fn audited<T, E: From<DbError>>(&mut self, mutation: impl FnOnce(&mut Db) -> Result<(T, NewAuditEntry), E>) -> Result<T, E> {
+        self.connection.execute_batch("SAVEPOINT audited").map_err(DbError::from)?;
+
+        let result = mutation(self).and_then(|(value, new_audit_entry)| {
+            self.insert_audit_entry(&new_audit_entry)?;
+            self.connection.execute_batch("RELEASE audited").map_err(DbError::from)?;
+            Ok(value)
+        });
+
+        if result.is_err() {
+            self.connection.execute_batch("ROLLBACK TO audited; RELEASE audited").unwrap_or(());
+        }
+
+        result
+    }
+
+    pub fn remove_lap(&mut self, lap_id: i64) -> Result<Lap, DbError> {
+        self.set_lap_deleted(lap_id, true)
+    }
+
+    pub fn restore_lap(&mut self, lap_id: i64) -> Result<Lap, DbError> {
+        self.set_lap_deleted(lap_id, false)
+    }
+
+    fn set_lap_deleted(&mut self, lap_id: i64, deleted: bool) -> Result<Lap, DbError> {
+        let tx = self.connection.savepoint()?;
+
+        tx.execute(
+            "UPDATE laps SET deleted = ?1 WHERE id = ?2",
+            params![deleted, lap_id]
+        )?;
+
+        let heat_id = find_lap(&tx, lap_id)?.heat_id;
+        renumber_laps(&tx, heat_id)?;
+        let lap = find_lap(&tx, lap_id)?;
+
+        tx.commit()?;
+
+        Ok(lap)
+    }
+}
+
+fn insert_race_with_heats(connection: &Connection, new_race_dto: NewRaceDto) -> Result<Race, DbError> {
+    connection.execute(
+        "INSERT INTO races (name, status, format, round_id) VALUES (?1, ?2, ?3, ?4)",
+        params![new_race_dto.name, RaceStatus::New.to_string(), new_race_dto.format, new_race_dto.round_id]
+    )?;
+
+    let new_race_id = connection.last_insert_rowid();
+    let mut heats = Vec::with_capacity(new_race_dto.heats.len());
+
+    for heat in &new_race_dto.heats {
+        let channel = heat.channel.ok_or_else(|| DbError::Invalid(format!("Heat {} of race '{}' has no channel", heat.no, new_race_dto.name)))?;
+
+        connection.execute(
+            "INSERT INTO heats (no, channel, pilot_id, race_id, rssi_raw) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![heat.no, channel, heat.pilot_id, new_race_id, ""]
+        )?;
+
+        heats.push(Heat::new(connection.last_insert_rowid(), heat.no, channel, heat.pilot_id, Vec::new()));
+    }
+
+    Ok(Race::new(new_race_id, new_race_dto.name, RaceStatus::New, heats, new_race_dto.format, None, None, new_race_dto.round_id))
+}
+
+const PILOT_PROFILE_COLUMNS: &str =
+    "id, callsign, real_name, country, team, video_system, preferred_channel, transponder, phonetic_name, avatar_path";
+
+fn map_pilot(row: &rusqlite::Row) -> rusqlite::Result<Pilot> {
+    Ok(Pilot::new(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+}
+
+fn map_pilot_profile(row: &rusqlite::Row) -> rusqlite::Result<PilotProfile> {
+    Ok(PilotProfile {
+        id: row.get(0)?,
+        callsign: row.get(1)?,
+        real_name: row.get(2)?,
+        country: row.get(3)?,
+        team: row.get(4)?,
+        video_system: row.get(5)?,
+        preferred_channel: row.get(6)?,
+        transponder: row.get(7)?,
+        phonetic_name: row.get(8)?,
+        avatar_path: row.get(9)?,
+    })
+}
+
+fn map_round(row: &rusqlite::Row) -> rusqlite::Result<Round> {
+    Ok(Round::new(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+}
+
+fn map_lap(row: &rusqlite::Row) -> rusqlite::Result<Lap> {
+    Ok(Lap::new(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?))
+}
+
+fn find_laps(connection: &Connection, heat_id: i64) -> rusqlite::Result<Vec<Lap>> {
+    let mut statement = connection.prepare(
+        "SELECT id, heat_id, no, crossed_at, lap_time, source, deleted FROM laps WHERE heat_id = ?1 ORDER BY crossed_at, id"
+    )?;
+
+    let laps = statement.query_map([heat_id], map_lap)?.collect();
+
+    laps
+}
+
+fn find_lap(connection: &Connection, lap_id: i64) -> rusqlite::Result<Lap> {
+    connection.query_row(
+        "SELECT id, heat_id, no, crossed_at, lap_time, source, deleted FROM laps WHERE id = ?1",
+        [lap_id],
+        map_lap
+    )
+}
+
+// Lap numbers and lap times are derived from the crossings that are not deleted, so they have to be
+// recalculated whenever a lap is added, deleted or restored. The first crossing is the holeshot and gets no 0.
+fn renumber_laps(connection: &Connection, heat_id: i64) -> rusqlite::Result<()> {
+    let mut statement = connection.prepare(
+        "SELECT id, crossed_at FROM laps WHERE heat_id = ?1 AND deleted = 0 ORDER BY crossed_at, id"
+    )?;
+
+    let laps: Vec<(i64, i64)> = statement.query_map([heat_id], |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })?.collect::<rusqlite::Result<_>>()?;
+
+    let mut previous_crossed_at = 0;
+
+    for (index, (lap_id, crossed_at)) in laps.iter().enumerate() {
+        connection.execute(
+            "UPDATE laps SET no = ?1, lap_time = ?2 WHERE id = ?3",
+            params![index as u32, crossed_at - previous_crossed_at, lap_id]
+        )?;
+
+        previous_crossed_at = *crossed_at;
+    }
+
+    Ok(())
 }
@@ -1,77 +1,844 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Write};
+use std::fmt;
+use std::fmt::Formatter;
+use std::collections::VecDeque;
+use std::env;
+use std::future::Future;
+use std::io;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::pin::Pin;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
 use std::time::Duration;
-use serialport::SerialPort;
+#[cfg(unix)]
+use serialport::{SerialPort, TTYPort};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 use serialport::SerialPortType::UsbPort;
-use tokio::sync::mpsc::Receiver;
+use tauri::AppHandle;
+use tokio::select;
+use tokio::sync::mpsc::{channel, unbounded_channel, Receiver, Sender, UnboundedReceiver, UnboundedSender};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::oneshot;
+use crate::events;
+use crate::events::AppEvent;
+use crate::protocol::{parse_line, DeviceEvent, ParseError};
+use crate::simulator::{Simulator, SimulatorSettings};
 
-#[derive(Debug)]
+const READ_TIMEOUT: Duration = Duration::from_millis(1000);
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(3000);
+const ACK_TIMEOUT: Duration = Duration::from_millis(1000);
+const CALIBRATION_TIMEOUT: Duration = Duration::from_millis(5000);
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(2000);
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum Commands {
-    StartRace
+    StartRace,
+    StopRace,
+    AbortRace,
+    SetFrequency { node: u8, frequency: u16 },
+    SetThresholds { node: u8, enter: u16, exit: u16 },
+    Calibrate { node: u8 },
+    GetVersion,
+    Ping,
+}
+
+impl Commands {
+    pub fn to_wire(&self) -> String {
+        match self {
+            Commands::StartRace => "r:s".to_string(),
+            Commands::StopRace => "r:e".to_string(),
+            Commands::AbortRace => "r:a".to_string(),
+            Commands::SetFrequency { node, frequency } => format!("f:{}:{}", node, frequency),
+            Commands::SetThresholds { node, enter, exit } => format!("t:{}:{}:{}", node, enter, exit),
+            Commands::Calibrate { node } => format!("c:{}", node),
+            Commands::GetVersion => "v".to_string(),
+            Commands::Ping => "p".to_string(),
+        }
+    }
+
+    pub fn is_acknowledged_by(&self, event: &DeviceEvent) -> bool {
+        match (self, event) {
+            (Commands::GetVersion, DeviceEvent::Version { .. }) => true,
+            (_, DeviceEvent::Ack { command }) => *command == self.to_wire(),
+            _ => false,
+        }
+    }
+
+    pub fn ack_timeout(&self) -> Duration {
+        match self {
+            Commands::Calibrate { .. } => CALIBRATION_TIMEOUT,
+            _ => ACK_TIMEOUT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceError {
+    NotConnected,
+    Timeout(Commands),
+    Rejected(String),
+    Io(String),
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::NotConnected => write!(f, "Timer device is not connected"),
+            DeviceError::Timeout(command) => {
+                write!(f, "Timer device did not acknowledge '{}' command", command.to_wire())
+            }
+            DeviceError::Rejected(message) => write!(f, "Timer device rejected the command: {}", message),
+            DeviceError::Io(message) => write!(f, "Timer device communication failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+#[derive(Debug)]
+pub struct DeviceRequest {
+    pub command: Commands,
+    response_tx: oneshot::Sender<Result<DeviceEvent, DeviceError>>,
+}
+
+impl DeviceRequest {
+    pub fn new(command: Commands) -> (DeviceRequest, oneshot::Receiver<Result<DeviceEvent, DeviceError>>) {
+        let (sender, receiver) = oneshot::channel();
+        (
+            DeviceRequest {
+                command,
+                response_tx: sender,
+            },
+            receiver,
+        )
+    }
+
+    pub fn respond(self, result: Result<DeviceEvent, DeviceError>) {
+        self.response_tx.send(result).unwrap_or(());
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PortInfo {
+    pub port_name: String,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+}
+
+pub fn get_available_devices() -> Vec<PortInfo> {
+    serialport::available_ports()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|port| match port.port_type {
+            UsbPort(usb) => PortInfo {
+                port_name: port.port_name,
+                vid: Some(usb.vid),
+                pid: Some(usb.pid),
+                serial_number: usb.serial_number,
+                manufacturer: usb.manufacturer,
+                product: usb.product,
+            },
+            _ => PortInfo {
+                port_name: port.port_name,
+                vid: None,
+                pid: None,
+                serial_number: None,
+                manufacturer: None,
+                product: None,
+            },
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum DeviceSelection {
+    Serial { port_name: String, baud_rate: u32 },
+    Tcp { address: String },
+    Simulator {
+        #[serde(default)]
+        settings: SimulatorSettings,
+    },
 }
 
-pub fn get_available_devices() -> Vec<String> {
-    let mut ports = Vec::new();
+impl FromSql for DeviceSelection {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().and_then(|s| {
+            serde_json::from_str(s).map_err(|e| FromSqlError::Other(Box::new(e)))
+        })
+    }
+}
+
+impl ToSql for DeviceSelection {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        serde_json::to_string(self)
+            .map(ToSqlOutput::from)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+    }
+}
+
+impl DeviceSelection {
+    pub fn from_env() -> Option<DeviceSelection> {
+        if env::var("PATLIMER_SIMULATOR").is_ok() {
+            return Some(DeviceSelection::Simulator { settings: SimulatorSettings::default() });
+        }
+
+        env::var("PATLIMER_TIMER_ADDRESS")
+            .ok()
+            .map(|address| DeviceSelection::Tcp { address })
+    }
+
+    // Serial ports disappear when the timer is unplugged, there is no point in opening them until they are back.
+    fn is_available(&self) -> bool {
+        match self {
+            DeviceSelection::Serial { port_name, .. } => {
+                get_available_devices().iter().any(|port| port.port_name == *port_name)
+            }
+            _ => true,
+        }
+    }
 
-    for port in serialport::available_ports().unwrap() {
-        match port.port_type {
-            UsbPort(t) => ports.push(port.port_name),
-            _ => (),
+    fn create_device(&self) -> Box<dyn TimerDevice> {
+        match self {
+            DeviceSelection::Serial { port_name, baud_rate } => {
+                Box::new(LineDevice::new(SerialTransport::new(port_name.clone(), *baud_rate)))
+            }
+            DeviceSelection::Tcp { address } => Box::new(LineDevice::new(TcpTransport::new(address.clone()))),
+            DeviceSelection::Simulator { settings } => Box::new(LineDevice::new(Simulator::new(settings.clone()))),
         }
     }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub enum ConnectionState {
+    Disconnected,
+    Connected,
+    Reconnecting,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ConnectionStatus {
+    pub state: ConnectionState,
+    pub selection: Option<DeviceSelection>,
+    pub capabilities: Option<Capabilities>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum DeviceControl {
+    Connect(DeviceSelection, oneshot::Sender<Result<ConnectionStatus, DeviceError>>),
+    Disconnect(oneshot::Sender<ConnectionStatus>),
+    Status(oneshot::Sender<ConnectionStatus>),
+}
+
+pub type Reader = Box<dyn BufRead + Send>;
+pub type Writer = Box<dyn Write + Send>;
+pub type CommandFuture<'a> = Pin<Box<dyn Future<Output = Result<DeviceEvent, DeviceError>> + Send + 'a>>;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Capabilities {
+    pub nodes: Option<u8>,
+    pub lap_detection: bool,
+    pub rssi_streaming: bool,
+    pub frequency_control: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities {
+            nodes: None,
+            lap_detection: true,
+            rssi_streaming: true,
+            frequency_control: true,
+        }
+    }
+}
+
+pub trait Transport: Send {
+    fn open(&mut self) -> io::Result<(Reader, Writer)>;
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+}
+
+pub trait TimerDevice: Send {
+    fn connect(&mut self) -> Result<Receiver<DeviceEvent>, DeviceError>;
+    fn disconnect(&mut self);
+    fn is_connected(&self) -> bool;
+    fn send_command(&mut self, command: Commands) -> CommandFuture<'_>;
+    fn capabilities(&self) -> Capabilities;
+}
+
+pub struct SerialTransport {
+    port_name: String,
+    baud_rate: u32,
+}
+
+impl SerialTransport {
+    pub fn new(port_name: String, baud_rate: u32) -> SerialTransport {
+        SerialTransport { port_name, baud_rate }
+    }
+}
+
+impl Transport for SerialTransport {
+    fn open(&mut self) -> io::Result<(Reader, Writer)> {
+        let port = serialport::new(&self.port_name, self.baud_rate)
+            .timeout(READ_TIMEOUT)
+            .open()?;
+        let port_clone = port.try_clone()?;
+
+        Ok((Box::new(BufReader::new(port_clone)), Box::new(port)))
+    }
+}
+
+pub struct TcpTransport {
+    address: String,
+}
+
+impl TcpTransport {
+    pub fn new(address: String) -> TcpTransport {
+        TcpTransport { address }
+    }
+}
+
+impl Transport for TcpTransport {
+    fn open(&mut self) -> io::Result<(Reader, Writer)> {
+        let address = self.address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("Can not resolve '{}'", self.address)))?;
+        let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        let stream_clone = stream.try_clone()?;
+
+        Ok((Box::new(BufReader::new(stream_clone)), Box::new(stream)))
+    }
+}
+
+pub struct ChannelReader {
+    bytes_rx: std::sync::mpsc::Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+}
+
+impl ChannelReader {
+    pub fn new(bytes_rx: std::sync::mpsc::Receiver<Vec<u8>>) -> ChannelReader {
+        ChannelReader {
+            bytes_rx,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.bytes_rx.recv_timeout(READ_TIMEOUT) {
+                Ok(bytes) => self.pending = bytes,
+                Err(RecvTimeoutError::Timeout) => return Err(ErrorKind::TimedOut.into()),
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            }
+        }
+
+        let length = buf.len().min(self.pending.len());
+        buf[..length].copy_from_slice(&self.pending[..length]);
+        self.pending.drain(..length);
+
+        Ok(length)
+    }
+}
+
+pub struct ChannelWriter {
+    bytes_tx: std::sync::mpsc::Sender<Vec<u8>>,
+}
+
+impl ChannelWriter {
+    pub fn new(bytes_tx: std::sync::mpsc::Sender<Vec<u8>>) -> ChannelWriter {
+        ChannelWriter { bytes_tx }
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes_tx
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::from(ErrorKind::BrokenPipe))?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The other end of a `MemoryTransport`: whatever is written here is read by the app as timer
+// output, and commands sent by the app can be read from here.
+pub struct MemoryPeer {
+    pub reader: BufReader<ChannelReader>,
+    pub writer: ChannelWriter,
+}
+
+pub struct MemoryTransport {
+    ends: Option<(ChannelReader, ChannelWriter)>,
+}
+
+impl MemoryTransport {
+    pub fn pair() -> (MemoryTransport, MemoryPeer) {
+        let (output_tx, output_rx) = std::sync::mpsc::channel();
+        let (input_tx, input_rx) = std::sync::mpsc::channel();
+
+        (
+            MemoryTransport {
+                ends: Some((ChannelReader::new(output_rx), ChannelWriter::new(input_tx))),
+            },
+            MemoryPeer {
+                reader: BufReader::new(ChannelReader::new(input_rx)),
+                writer: ChannelWriter::new(output_tx),
+            },
+        )
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn open(&mut self) -> io::Result<(Reader, Writer)> {
+        let (reader, writer) = self.ends
+            .take()
+            .ok_or_else(|| io::Error::new(ErrorKind::AddrInUse, "Memory transport can be opened only once"))?;
+
+        Ok((Box::new(BufReader::new(reader)), Box::new(writer)))
+    }
+}
+
+// The app talks to the master side of a pseudo-terminal, the slave side behaves like a serial port
+// and can be opened by name by anything emulating a timer.
+#[cfg(unix)]
+pub struct PtyTransport {
+    master: TTYPort,
+    slave: Option<TTYPort>,
+    slave_name: Option<String>,
+}
+
+#[cfg(unix)]
+impl PtyTransport {
+    pub fn new() -> io::Result<PtyTransport> {
+        let (mut master, slave) = TTYPort::pair()?;
+        master.set_timeout(READ_TIMEOUT)?;
+        let slave_name = slave.name();
+
+        Ok(PtyTransport {
+            master,
+            slave: Some(slave),
+            slave_name,
+        })
+    }
+
+    pub fn slave_name(&self) -> Option<String> {
+        self.slave_name.clone()
+    }
+
+    pub fn take_slave(&mut self) -> Option<TTYPort> {
+        self.slave.take()
+    }
+}
+
+#[cfg(unix)]
+impl Transport for PtyTransport {
+    fn open(&mut self) -> io::Result<(Reader, Writer)> {
+        let reader = self.master.try_clone_native()?;
+        let writer = self.master.try_clone_native()?;
+
+        Ok((Box::new(BufReader::new(reader)), Box::new(writer)))
+    }
+}
 
-    ports
+struct Connection {
+    writer: Writer,
+    acks_rx: UnboundedReceiver<DeviceEvent>,
 }
 
-pub fn connect_to_device(port_name: String) -> Box<dyn SerialPort> {
-    serialport::new(port_name, 115_200)
-        .timeout(Duration::from_millis(1000))
-        .open()
-        .expect("Failed to open port")
+pub struct LineDevice<T: Transport> {
+    transport: T,
+    connection: Option<Connection>,
+}
+
+impl<T: Transport> LineDevice<T> {
+    pub fn new(transport: T) -> LineDevice<T> {
+        LineDevice {
+            transport,
+            connection: None,
+        }
+    }
+}
+
+impl<T: Transport> TimerDevice for LineDevice<T> {
+    fn connect(&mut self) -> Result<Receiver<DeviceEvent>, DeviceError> {
+        let (reader, writer) = self.transport
+            .open()
+            .map_err(|e| DeviceError::Io(e.to_string()))?;
+        let (events_tx, events_rx) = channel(1000);
+        let (acks_tx, acks_rx) = unbounded_channel();
+
+        thread::spawn(move || read_data(reader, events_tx, acks_tx));
+
+        self.connection = Some(Connection { writer, acks_rx });
+
+        Ok(events_rx)
+    }
+
+    fn disconnect(&mut self) {
+        self.connection = None;
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    fn send_command(&mut self, command: Commands) -> CommandFuture<'_> {
+        Box::pin(async move {
+            let connection = self.connection.as_mut().ok_or(DeviceError::NotConnected)?;
+            send_command(&mut connection.writer, &mut connection.acks_rx, &command).await
+        })
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.transport.capabilities()
+    }
 }
 
-pub fn read_data(mut reader: BufReader<Box<dyn SerialPort>>) {
+pub fn read_data(
+    mut reader: Reader,
+    events_tx: Sender<DeviceEvent>,
+    acks_tx: UnboundedSender<DeviceEvent>,
+) {
     let mut my_str = String::new();
 
     loop {
         match reader.read_line(&mut my_str) {
-            Ok(t) => {
-                let parts = my_str.split(":");
-                let timestamp: Vec<&str> = parts.collect();
-                if timestamp.len() > 0 {
+            Ok(0) => break,
+            Ok(_) => {
+                match parse_line(&my_str) {
+                    Ok(event) => {
+                        if let DeviceEvent::Ack { .. } | DeviceEvent::Version { .. } | DeviceEvent::Error { .. } = event {
+                            acks_tx.send(event.clone()).unwrap_or(());
+                        }
+                        if events_tx.blocking_send(event).is_err() {
+                            break;
+                        }
+                    }
+                    Err(ParseError::Empty) => (),
+                    Err(e) => {
+                        let message = format!("Malformed line '{}': {}", my_str.trim(), e);
+                        if events_tx.blocking_send(DeviceEvent::Error { message }).is_err() {
+                            break;
+                        }
+                    }
                 }
-                println!("{}", my_str);
                 my_str.clear();
             },
-            Err(e) => println!("{}", e),
+            Err(e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock => {
+                if events_tx.is_closed() {
+                    break;
+                }
+            },
+            Err(e) => {
+                events_tx.blocking_send(DeviceEvent::Error { message: e.to_string() }).unwrap_or(());
+                break;
+            },
         }
     }
 }
 
-pub async fn process_data(mut commands_rx: Receiver<Commands>) {
-    let mut ports = get_available_devices();
+async fn send_command(
+    writer: &mut Writer,
+    acks_rx: &mut UnboundedReceiver<DeviceEvent>,
+    command: &Commands,
+) -> Result<DeviceEvent, DeviceError> {
+    while acks_rx.try_recv().is_ok() {}
+
+    let output = format!("{}\n", command.to_wire());
+    writer.write_all(output.as_bytes())
+        .and_then(|_| writer.flush())
+        .map_err(|e| DeviceError::Io(e.to_string()))?;
+
+    let wait_for_ack = async {
+        while let Some(event) = acks_rx.recv().await {
+            if command.is_acknowledged_by(&event) {
+                return Ok(event);
+            }
+            if let DeviceEvent::Error { message } = event {
+                return Err(DeviceError::Rejected(message));
+            }
+        }
+        Err(DeviceError::NotConnected)
+    };
 
-    if ports.len() > 0 {
-        let mut port = connect_to_device(ports.pop().unwrap());
-        let mut port_clone = port.try_clone().unwrap();
+    tokio::time::timeout(command.ack_timeout(), wait_for_ack)
+        .await
+        .unwrap_or_else(|_| Err(DeviceError::Timeout(command.clone())))
+}
 
-        tauri::async_runtime::spawn(async move {
-            while let Some(command) = commands_rx.recv().await {
-                match command {
-                    Commands::StartRace => {
-                        let output = "r:s\n".as_bytes();
-                        port.write(output).expect("Writing failed.");
+async fn next_event(events_rx: &mut Option<Receiver<DeviceEvent>>) -> Option<DeviceEvent> {
+    match events_rx {
+        Some(events_rx) => events_rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+// The selection is kept after the device is lost, so it can be reopened as soon as it comes back.
+#[derive(Default)]
+struct ActiveDevice {
+    selection: Option<DeviceSelection>,
+    device: Option<Box<dyn TimerDevice>>,
+    events_rx: Option<Receiver<DeviceEvent>>,
+    error: Option<String>,
+}
+
+impl ActiveDevice {
+    fn connect(&mut self, selection: DeviceSelection) -> Result<(), DeviceError> {
+        self.disconnect();
+
+        let mut device = selection.create_device();
+        self.events_rx = Some(device.connect()?);
+        self.device = Some(device);
+        self.selection = Some(selection);
+        self.error = None;
+
+        Ok(())
+    }
+
+    fn reconnect(&mut self) -> Result<(), DeviceError> {
+        let mut device = self.selection.as_ref().ok_or(DeviceError::NotConnected)?.create_device();
+        self.events_rx = Some(device.connect()?);
+        self.device = Some(device);
+        self.error = None;
+
+        Ok(())
+    }
+
+    fn lost(&mut self) {
+        self.events_rx = None;
+        if let Some(mut device) = self.device.take() {
+            device.disconnect();
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.lost();
+        self.selection = None;
+        self.error = None;
+    }
+
+    fn should_reconnect(&self) -> bool {
+        self.device.is_none() && self.selection.as_ref().map_or(false, |selection| selection.is_available())
+    }
+
+    fn status(&self) -> ConnectionStatus {
+        let state = match (&self.device, &self.selection) {
+            (Some(device), _) if device.is_connected() => ConnectionState::Connected,
+            (_, Some(_)) => ConnectionState::Reconnecting,
+            _ => ConnectionState::Disconnected,
+        };
+
+        ConnectionStatus {
+            state,
+            selection: self.selection.clone(),
+            capabilities: self.device.as_ref().map(|device| device.capabilities()),
+            error: self.error.clone(),
+        }
+    }
+}
+
+fn emit_status(app_handle: &AppHandle, status: ConnectionStatus) {
+    events::emit(app_handle, AppEvent::DeviceStatus(status));
+}
+
+pub async fn process_data(
+    mut commands_rx: Receiver<DeviceRequest>,
+    mut control_rx: Receiver<DeviceControl>,
+    events_tx: Sender<DeviceEvent>,
+    app_handle: AppHandle,
+    selection: Option<DeviceSelection>,
+) {
+    let mut active_device = ActiveDevice {
+        selection,
+        ..ActiveDevice::default()
+    };
+    let mut reconnect_ticker = tokio::time::interval(RECONNECT_INTERVAL);
+    let mut pending_events = VecDeque::new();
+
+    loop {
+        select! {
+            request = commands_rx.recv() => match request {
+                Some(request) => {
+                    let result = match active_device.device.as_mut() {
+                        Some(device) if device.is_connected() => device.send_command(request.command.clone()).await,
+                        _ => Err(DeviceError::NotConnected),
+                    };
+                    request.respond(result);
+                }
+                None => break,
+            },
+            control = control_rx.recv() => match control {
+                Some(DeviceControl::Connect(selection, response_tx)) => {
+                    let result = active_device.connect(selection).map(|_| active_device.status());
+                    emit_status(&app_handle, active_device.status());
+                    response_tx.send(result).unwrap_or(());
+                }
+                Some(DeviceControl::Disconnect(response_tx)) => {
+                    active_device.disconnect();
+                    emit_status(&app_handle, active_device.status());
+                    response_tx.send(active_device.status()).unwrap_or(());
+                }
+                Some(DeviceControl::Status(response_tx)) => {
+                    response_tx.send(active_device.status()).unwrap_or(());
+                }
+                None => break,
+            },
+            event = next_event(&mut active_device.events_rx) => match event {
+                Some(event) => {
+                    if let DeviceEvent::Error { message } = &event {
+                        active_device.error = Some(message.clone());
+                        emit_status(&app_handle, active_device.status());
                     }
+
+                    // Core stops reading events while it waits for a command, so forwarding must never block here.
+                    // RSSI is dropped when core is behind, anything else waits in order until there is room.
+                    if !pending_events.is_empty() {
+                        if !matches!(event, DeviceEvent::Rssi { .. }) {
+                            pending_events.push_back(event);
+                        }
+                    } else {
+                        match events_tx.try_send(event) {
+                            Ok(_) | Err(TrySendError::Full(DeviceEvent::Rssi { .. })) => (),
+                            Err(TrySendError::Full(event)) => pending_events.push_back(event),
+                            Err(TrySendError::Closed(_)) => break,
+                        }
+                    }
+                }
+                None => {
+                    active_device.lost();
+                    active_device.error = Some("Timer device disconnected".to_string());
+                    emit_status(&app_handle, active_device.status());
+                }
+            },
+            permit = events_tx.reserve(), if !pending_events.is_empty() => match permit {
+                Ok(permit) => permit.send(pending_events.pop_front().unwrap()),
+                Err(_) => break,
+            },
+            _ = reconnect_ticker.tick(), if active_device.should_reconnect() => {
+                match active_device.reconnect() {
+                    Ok(_) => emit_status(&app_handle, active_device.status()),
+                    Err(e) => {
+                        let error = Some(format!("Can not connect to the timer device: {}", e));
+                        if active_device.error != error {
+                            active_device.error = error;
+                            emit_status(&app_handle, active_device.status());
+                        }
+                    }
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Plays the timer: reads one command and answers with `lines`.
+    fn answer(mut peer: MemoryPeer, lines: &'static str) -> thread::JoinHandle<(MemoryPeer, String)> {
+        thread::spawn(move || {
+            let mut command = String::new();
+            loop {
+                match peer.reader.read_line(&mut command) {
+                    Ok(_) => break,
+                    Err(e) if e.kind() == ErrorKind::TimedOut => continue,
+                    Err(e) => panic!("{}", e),
                 }
-                dbg!(command);
             }
-        });
+            peer.writer.write_all(lines.as_bytes()).unwrap();
+            (peer, command.trim().to_string())
+        })
+    }
 
-        tauri::async_runtime::spawn(async move {
-            let mut reader = BufReader::new(port_clone);
-            read_data(reader);
-        });
+    #[tokio::test]
+    async fn waits_for_the_matching_ack() {
+        let (transport, peer) = MemoryTransport::pair();
+        let mut device = LineDevice::new(transport);
+        let mut events_rx = device.connect().unwrap();
+        let timer = answer(peer, "a:r:s\nl:1:100\na:p\n");
+
+        let result = device.send_command(Commands::Ping).await;
+        let (_peer, command) = timer.join().unwrap();
 
+        assert_eq!(command, "p");
+        assert_eq!(result, Ok(DeviceEvent::Ack { command: "p".to_string() }));
+        assert_eq!(events_rx.recv().await, Some(DeviceEvent::Ack { command: "r:s".to_string() }));
+        assert_eq!(events_rx.recv().await, Some(DeviceEvent::Lap { node: 1, timestamp: 100 }));
+    }
+
+    #[tokio::test]
+    async fn times_out_without_an_ack() {
+        let (transport, peer) = MemoryTransport::pair();
+        let mut device = LineDevice::new(transport);
+        let _events_rx = device.connect().unwrap();
+        let timer = answer(peer, "h:1000\n");
+
+        let result = device.send_command(Commands::StartRace).await;
+        let _peer = timer.join().unwrap();
+
+        assert_eq!(result, Err(DeviceError::Timeout(Commands::StartRace)));
+    }
+
+    #[tokio::test]
+    async fn is_rejected_by_an_error_line() {
+        let (transport, peer) = MemoryTransport::pair();
+        let mut device = LineDevice::new(transport);
+        let _events_rx = device.connect().unwrap();
+        let timer = answer(peer, "e:Node 9 does not exist\n");
+
+        let result = device.send_command(Commands::SetFrequency { node: 9, frequency: 5658 }).await;
+        let (_peer, command) = timer.join().unwrap();
+
+        assert_eq!(command, "f:9:5658");
+        assert_eq!(result, Err(DeviceError::Rejected("Node 9 does not exist".to_string())));
+    }
+
+    #[tokio::test]
+    async fn needs_a_connection() {
+        let (transport, _peer) = MemoryTransport::pair();
+        let mut device = LineDevice::new(transport);
+
+        assert_eq!(device.send_command(Commands::Ping).await, Err(DeviceError::NotConnected));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn talks_to_a_pseudo_terminal() {
+        let mut transport = PtyTransport::new().unwrap();
+        assert!(transport.slave_name().is_some());
+        let mut slave = transport.take_slave().unwrap();
+        slave.set_timeout(Duration::from_secs(5)).unwrap();
+        let mut device = LineDevice::new(transport);
+        let mut events_rx = device.connect().unwrap();
+
+        let timer = thread::spawn(move || {
+            let mut command = [0u8; 4];
+            slave.read_exact(&mut command).unwrap();
+            slave.write_all(b"l:2:5\na:r:s\n").unwrap();
+            (slave, command)
+        });
+        let result = device.send_command(Commands::StartRace).await;
+        let (_slave, command) = timer.join().unwrap();
 
+        assert_eq!(&command, b"r:s\n");
+        assert_eq!(result, Ok(DeviceEvent::Ack { command: "r:s".to_string() }));
+        assert_eq!(events_rx.recv().await, Some(DeviceEvent::Lap { node: 2, timestamp: 5 }));
+        device.disconnect();
     }
 }
@@ -0,0 +1,98 @@
+use tauri::{AppHandle, Manager};
+use crate::core::{Lap, Race};
+use crate::device::ConnectionStatus;
+use crate::leaderboard::LeaderboardEntry;
+use crate::start_sequence::StartSequenceEvent;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LapRecordedEvent {
+    pub race_event_id: i64,
+    pub race_id: i64,
+    pub lap: Lap,
+    pub laps: Vec<Lap>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LapsCorrectedEvent {
+    pub race_event_id: i64,
+    pub race_id: i64,
+    pub heat_id: i64,
+    pub laps: Vec<Lap>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LeaderboardUpdatedEvent {
+    pub race_event_id: i64,
+    pub race_id: i64,
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RaceStatusChangedEvent {
+    pub race_event_id: i64,
+    pub race: Race,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RacesUpdatedEvent {
+    pub race_event_id: i64,
+    pub races: Vec<Race>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RssiSample {
+    pub node: u8,
+    pub timestamp: u64,
+    pub rssi: u16,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RssiBatchEvent {
+    pub samples: Vec<RssiSample>,
+}
+
+#[derive(Debug, Clone)]
+pub enum AppEvent {
+    LapRecorded(LapRecordedEvent),
+    LapsCorrected(LapsCorrectedEvent),
+    LeaderboardUpdated(LeaderboardUpdatedEvent),
+    RaceStatusChanged(RaceStatusChangedEvent),
+    RacesUpdated(RacesUpdatedEvent),
+    DeviceStatus(ConnectionStatus),
+    RssiBatch(RssiBatchEvent),
+    StartSequence(StartSequenceEvent),
+}
+
+impl AppEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AppEvent::LapRecorded(_) => "lap-recorded",
+            AppEvent::LapsCorrected(_) => "laps-corrected",
+            AppEvent::LeaderboardUpdated(_) => "leaderboard-updated",
+            AppEvent::RaceStatusChanged(_) => "race-status-changed",
+            AppEvent::RacesUpdated(_) => "races-updated",
+            AppEvent::DeviceStatus(_) => "device-status",
+            AppEvent::RssiBatch(_) => "rssi-batch",
+            AppEvent::StartSequence(_) => "start-sequence",
+        }
+    }
+}
+
+pub fn emit(app_handle: &AppHandle, event: AppEvent) {
+    let name = event.name();
+
+    let result = match event {
+        AppEvent::LapRecorded(payload) => app_handle.emit_all(name, payload),
+        AppEvent::LapsCorrected(payload) => app_handle.emit_all(name, payload),
+        AppEvent::LeaderboardUpdated(payload) => app_handle.emit_all(name, payload),
+        AppEvent::RaceStatusChanged(payload) => app_handle.emit_all(name, payload),
+        AppEvent::RacesUpdated(payload) => app_handle.emit_all(name, payload),
+        AppEvent::DeviceStatus(payload) => app_handle.emit_all(name, payload),
+        AppEvent::RssiBatch(payload) => app_handle.emit_all(name, payload),
+        AppEvent::StartSequence(payload) => app_handle.emit_all(name, payload),
+    };
+
+    if let Err(e) = result {
+        println!("Can not emit '{}' event: {}", name, e);
+    }
+}
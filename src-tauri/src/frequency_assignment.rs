@@ -0,0 +1,188 @@
+use std::cmp::Reverse;
+use std::time::{Duration, Instant};
+use crate::channels;
+use crate::channels::{Channel, InterferenceSettings};
+use crate::core::Heat;
+use crate::device::Commands;
+
+// Line-ups of 8 analog pilots have a lot of combinations and races are planned while the app waits, the
+// search stops after this long and keeps the best line-up found so far.
+const TIME_LIMIT: Duration = Duration::from_millis(50);
+
+struct Search<'a> {
+    candidates: Vec<Vec<Channel>>,
+    preferred: &'a [Option<Channel>],
+    settings: &'a InterferenceSettings,
+    min_separation: u16,
+    chosen: Vec<Channel>,
+    best: Option<((u32, usize, Reverse<u16>), Vec<Channel>)>,
+    deadline: Instant,
+}
+
+fn separation(channels: &[Channel]) -> u16 {
+    channels
+        .iter()
+        .enumerate()
+        .flat_map(|(index, a)| channels[index + 1..].iter().map(move |b| a.frequency().abs_diff(b.frequency())))
+        .min()
+        .unwrap_or(u16::MAX)
+}
+
+impl<'a> Search<'a> {
+    fn visit(&mut self, index: usize) {
+        if Instant::now() >= self.deadline {
+            return;
+        }
+
+        if index == self.candidates.len() {
+            let missed = self.chosen
+                .iter()
+                .zip(self.preferred)
+                .filter(|(chosen, preferred)| preferred.map_or(false, |preferred| preferred.frequency() != chosen.frequency()))
+                .count();
+            let score = (channels::imd_score(&self.chosen, self.settings), missed, Reverse(separation(&self.chosen)));
+            if self.best.as_ref().map_or(true, |(best, _)| score < *best) {
+                self.best = Some((score, self.chosen.clone()));
+            }
+            return;
+        }
+
+        // Pilots with the same options are interchangeable, trying their channels in ascending order only
+        // skips line-ups that were already scored.
+        let ascending = index > 0
+            && self.candidates[index] == self.candidates[index - 1]
+            && self.preferred[index] == self.preferred[index - 1];
+
+        for position in 0..self.candidates[index].len() {
+            let channel = self.candidates[index][position];
+
+            if ascending && channel.frequency() <= self.chosen[index - 1].frequency() {
+                continue;
+            }
+            if self.chosen.iter().any(|chosen| chosen.frequency().abs_diff(channel.frequency()) < self.min_separation) {
+                continue;
+            }
+
+            self.chosen.push(channel);
+            self.visit(index + 1);
+            self.chosen.pop();
+        }
+    }
+}
+
+// Picks one channel per pilot out of their candidates with the least intermodulation, between equally clean
+// line-ups the one giving most pilots their preferred channel wins. Line-ups keeping the minimal separation
+// are preferred, otherwise pilots are only kept off each other's frequency.
+pub fn assign(candidates: &[Vec<Channel>], preferred: &[Option<Channel>], settings: &InterferenceSettings) -> Option<Vec<Channel>> {
+    let candidates: Vec<Vec<Channel>> = candidates
+        .iter()
+        .map(|channels| {
+            let mut unique: Vec<Channel> = Vec::new();
+            for channel in channels {
+                if !unique.iter().any(|other| other.frequency() == channel.frequency()) {
+                    unique.push(*channel);
+                }
+            }
+            unique
+        })
+        .collect();
+
+    [settings.min_separation, 1].iter().find_map(|min_separation| {
+        let mut search = Search {
+            candidates: candidates.clone(),
+            preferred,
+            settings,
+            min_separation: *min_separation,
+            chosen: Vec::new(),
+            best: None,
+            deadline: Instant::now() + TIME_LIMIT,
+        };
+        search.visit(0);
+        search.best.map(|(_, channels)| channels)
+    })
+}
+
+pub fn frequency_commands(heats: &[Heat]) -> Vec<Commands> {
+    heats
+        .iter()
+        .map(|heat| Commands::SetFrequency { node: heat.no, frequency: heat.channel.frequency() })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::VideoSystem;
+
+    fn channel(code: &str) -> Channel {
+        code.parse().unwrap()
+    }
+
+    fn frequencies(channels: &[Channel]) -> Vec<u16> {
+        let mut frequencies: Vec<u16> = channels.iter().map(|channel| channel.frequency()).collect();
+        frequencies.sort();
+        frequencies
+    }
+
+    #[test]
+    fn assigns_distinct_frequencies() {
+        let candidates = vec![VideoSystem::Analog.channels(); 8];
+        let channels = assign(&candidates, &[None; 8], &InterferenceSettings::default()).unwrap();
+        let mut distinct = frequencies(&channels);
+        distinct.dedup();
+
+        assert_eq!(channels.len(), 8);
+        assert_eq!(distinct.len(), 8);
+    }
+
+    #[test]
+    fn keeps_the_minimal_separation_when_possible() {
+        let candidates = vec![VideoSystem::Analog.channels(); 4];
+        let channels = assign(&candidates, &[None; 4], &InterferenceSettings::default()).unwrap();
+
+        assert!(separation(&channels) >= InterferenceSettings::default().min_separation);
+        assert_eq!(channels::imd_score(&channels, &InterferenceSettings::default()), 0);
+    }
+
+    #[test]
+    fn keeps_a_free_preferred_channel() {
+        let candidates = vec![VideoSystem::Analog.channels(); 3];
+        let preferred = [Some(channel("R1")), None, Some(channel("F4"))];
+        let channels = assign(&candidates, &preferred, &InterferenceSettings::default()).unwrap();
+
+        assert_eq!(channels[0], channel("R1"));
+        assert_eq!(channels[2], channel("F4"));
+    }
+
+    #[test]
+    fn keeps_dji_pilots_in_the_dji_band() {
+        let candidates = vec![
+            VideoSystem::DjiO3.channels(),
+            VideoSystem::Analog.channels(),
+            VideoSystem::DjiO3.channels(),
+            VideoSystem::HdZero.channels(),
+        ];
+        let channels = assign(&candidates, &[None; 4], &InterferenceSettings::default()).unwrap();
+
+        assert!(VideoSystem::DjiO3.channels().contains(&channels[0]));
+        assert!(VideoSystem::DjiO3.channels().contains(&channels[2]));
+        assert!(VideoSystem::HdZero.channels().contains(&channels[3]));
+        assert_eq!(frequencies(&channels).windows(2).filter(|pair| pair[0] == pair[1]).count(), 0);
+    }
+
+    #[test]
+    fn keeps_hand_picked_channels() {
+        let candidates = vec![vec![channel("R1")], VideoSystem::Analog.channels()];
+        let channels = assign(&candidates, &[None, Some(channel("R1"))], &InterferenceSettings::default()).unwrap();
+
+        assert_eq!(channels[0], channel("R1"));
+        assert_ne!(channels[1].frequency(), channel("R1").frequency());
+    }
+
+    #[test]
+    fn gives_up_when_pilots_share_their_only_frequency() {
+        let candidates = vec![vec![channel("R1")], vec![channel("HDZ1")]];
+
+        assert_eq!(assign(&candidates, &[None, None], &InterferenceSettings::default()), None);
+    }
+}
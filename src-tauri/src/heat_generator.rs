@@ -0,0 +1,283 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use crate::channels::Channel;
+use crate::core::{NewHeatDto, NewRaceDto, Pilot};
+use crate::race_engine::RaceFormat;
+use crate::standings::Standing;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum Seeding {
+    RoundRobin,
+    Random { seed: u64 },
+    Ranking,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct HeatPlanDto {
+    pub race_event_id: i64,
+    pub nodes: u8,
+    #[serde(default)]
+    pub channels: Vec<Channel>,
+    pub seeding: Seeding,
+    #[serde(default = "default_rounds")]
+    pub rounds: u32,
+    #[serde(default)]
+    pub format: RaceFormat,
+    pub round_id: Option<i64>,
+}
+
+fn default_rounds() -> u32 {
+    1
+}
+
+impl HeatPlanDto {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.nodes == 0 {
+            return Err("At least one timer node is required".to_string());
+        }
+        if !self.channels.is_empty() && self.channels.len() < self.nodes as usize {
+            return Err(format!("{} timer nodes need at least {} channels", self.nodes, self.nodes));
+        }
+        if self.rounds == 0 {
+            return Err("At least one round is required".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+// Splits pilots into as few races as the nodes allow, with race sizes differing by at most one pilot.
+fn race_sizes(pilots: usize, nodes: usize) -> Vec<usize> {
+    let races = (pilots + nodes - 1) / nodes;
+
+    (0..races)
+        .map(|index| pilots / races + (index < pilots % races) as usize)
+        .collect()
+}
+
+// Deals pilots to races like cards. Every round shifts the n-th card of each deal by n races, so pilots
+// meet different opponents than in the previous rounds.
+fn distribute(pilots: &[i64], races: usize, round: u32) -> Vec<Vec<i64>> {
+    let mut distributed = vec![Vec::new(); races];
+
+    for (index, pilot_id) in pilots.iter().enumerate() {
+        distributed[(index % races + index / races * round as usize) % races].push(*pilot_id);
+    }
+
+    distributed
+}
+
+// Highest seeds race together in the last race of the round.
+fn group(pilots: &[i64], sizes: &[usize]) -> Vec<Vec<i64>> {
+    let mut races = Vec::new();
+    let mut start = 0;
+
+    for size in sizes.iter().rev() {
+        races.push(pilots[start..start + size].to_vec());
+        start += size;
+    }
+
+    races.reverse();
+    races
+}
+
+fn shares_pilots(a: &[i64], b: &[i64]) -> bool {
+    a.iter().any(|pilot_id| b.contains(pilot_id))
+}
+
+// Greedily picks the next race that has nobody from the previous one, so pilots get time to change batteries.
+fn avoid_back_to_back(races: Vec<Vec<i64>>, previous: Option<&Vec<i64>>) -> Vec<Vec<i64>> {
+    let mut remaining = races;
+    let mut ordered: Vec<Vec<i64>> = Vec::with_capacity(remaining.len());
+
+    while !remaining.is_empty() {
+        let last = ordered.last().or(previous);
+        let index = remaining
+            .iter()
+            .position(|race| last.map_or(true, |last| !shares_pilots(race, last)))
+            .unwrap_or(0);
+        ordered.push(remaining.remove(index));
+    }
+
+    ordered
+}
+
+pub fn generate(plan: &HeatPlanDto, pilots: &[Pilot], standings: &[Standing]) -> Vec<NewRaceDto> {
+    if pilots.is_empty() {
+        return Vec::new();
+    }
+
+    let mut pilot_ids: Vec<i64> = pilots.iter().map(|pilot| pilot.id).collect();
+    let sizes = race_sizes(pilot_ids.len(), plan.nodes as usize);
+    let mut rng = match plan.seeding {
+        Seeding::Random { seed } => Some(StdRng::seed_from_u64(seed)),
+        _ => None,
+    };
+
+    if let Seeding::Ranking = plan.seeding {
+        let rank = |pilot_id: &i64| {
+            standings
+                .iter()
+                .find(|standing| standing.pilot_id == *pilot_id)
+                .map_or(u32::MAX, |standing| standing.position)
+        };
+        pilot_ids.sort_by_key(|pilot_id| (rank(pilot_id), *pilot_id));
+    }
+
+    let mut new_races = Vec::new();
+    let mut previous: Option<Vec<i64>> = None;
+
+    for round in 0..plan.rounds {
+        let races = match (&plan.seeding, rng.as_mut()) {
+            (Seeding::Random { .. }, Some(rng)) => {
+                pilot_ids.shuffle(rng);
+                distribute(&pilot_ids, sizes.len(), 0)
+            }
+            (Seeding::Ranking, _) => group(&pilot_ids, &sizes),
+            _ => distribute(&pilot_ids, sizes.len(), round),
+        };
+
+        let races = avoid_back_to_back(races, previous.as_ref());
+        previous = races.last().cloned();
+
+        for (index, race) in races.into_iter().enumerate() {
+            let heats = race
+                .into_iter()
+                .enumerate()
+                .map(|(node, pilot_id)| NewHeatDto {
+                    no: node as u8 + 1,
+                    pilot_id,
+                    channel: plan.channels.get(node).copied(),
+                })
+                .collect();
+
+            new_races.push(NewRaceDto {
+                name: format!("Round {} Heat {}", round + 1, index + 1),
+                heats,
+                race_event_id: plan.race_event_id,
+                format: plan.format.clone(),
+                round_id: plan.round_id,
+            });
+        }
+    }
+
+    new_races
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::VideoSystem;
+
+    fn plan(nodes: u8, seeding: Seeding, rounds: u32) -> HeatPlanDto {
+        HeatPlanDto {
+            race_event_id: 1,
+            nodes,
+            channels: Vec::new(),
+            seeding,
+            rounds,
+            format: RaceFormat::default(),
+            round_id: None,
+        }
+    }
+
+    fn pilots(count: i64) -> Vec<Pilot> {
+        (1..=count)
+            .map(|id| Pilot::new(id, format!("Pilot {}", id), VideoSystem::Analog, None, None))
+            .collect()
+    }
+
+    fn sorted(races: &[Vec<i64>]) -> Vec<i64> {
+        let mut pilot_ids: Vec<i64> = races.iter().flatten().copied().collect();
+        pilot_ids.sort();
+        pilot_ids
+    }
+
+    #[test]
+    fn uses_as_few_races_as_the_nodes_allow() {
+        assert_eq!(race_sizes(8, 4), vec![4, 4]);
+        assert_eq!(race_sizes(10, 4), vec![4, 3, 3]);
+        assert_eq!(race_sizes(9, 8), vec![5, 4]);
+        assert_eq!(race_sizes(3, 4), vec![3]);
+    }
+
+    #[test]
+    fn deals_pilots_like_cards() {
+        let pilot_ids = [1, 2, 3, 4, 5, 6];
+
+        assert_eq!(distribute(&pilot_ids, 2, 0), vec![vec![1, 3, 5], vec![2, 4, 6]]);
+        assert_eq!(distribute(&pilot_ids, 2, 1), vec![vec![1, 4, 5], vec![2, 3, 6]]);
+    }
+
+    #[test]
+    fn deals_every_pilot_once_in_every_round() {
+        let pilot_ids: Vec<i64> = (1..=11).collect();
+
+        for round in 0..4 {
+            let races = distribute(&pilot_ids, 3, round);
+            let sizes: Vec<usize> = races.iter().map(|race| race.len()).collect();
+
+            assert_eq!(sorted(&races), pilot_ids);
+            assert!(sizes.iter().max().unwrap() - sizes.iter().min().unwrap() <= 1, "round {}: {:?}", round, sizes);
+        }
+    }
+
+    #[test]
+    fn keeps_pilots_out_of_back_to_back_races() {
+        let races = vec![vec![1, 3], vec![4, 5], vec![2, 6], vec![7, 8]];
+        let ordered = avoid_back_to_back(races.clone(), Some(&vec![1, 2]));
+
+        assert_eq!(ordered, vec![vec![4, 5], vec![1, 3], vec![2, 6], vec![7, 8]]);
+        assert_eq!(sorted(&ordered), sorted(&races));
+    }
+
+    #[test]
+    fn keeps_every_race_when_back_to_back_can_not_be_avoided() {
+        let races = vec![vec![1, 2], vec![1, 3], vec![1, 4]];
+
+        assert_eq!(avoid_back_to_back(races.clone(), None), races);
+    }
+
+    #[test]
+    fn generates_balanced_rounds() {
+        let races = generate(&plan(3, Seeding::RoundRobin, 2), &pilots(11), &[]);
+        let pilot_ids: Vec<Vec<i64>> = races
+            .iter()
+            .map(|race| race.heats.iter().map(|heat| heat.pilot_id).collect())
+            .collect();
+
+        assert_eq!(races.len(), 8);
+        assert_eq!(races[4].name, "Round 2 Heat 1");
+        assert!(pilot_ids.iter().all(|race| race.len() == 2 || race.len() == 3));
+        assert!(!shares_pilots(&pilot_ids[3], &pilot_ids[4]));
+        for round in pilot_ids.chunks(4) {
+            assert_eq!(sorted(round), (1..=11).collect::<Vec<i64>>());
+        }
+    }
+
+    #[test]
+    fn groups_the_highest_seeds_in_the_last_race() {
+        let standings: Vec<Standing> = [5, 6, 7, 8]
+            .iter()
+            .enumerate()
+            .map(|(index, pilot_id)| Standing {
+                position: index as u32 + 1,
+                pilot_id: *pilot_id,
+                points: 0,
+                races: 0,
+                wins: 0,
+                best_lap: None,
+                best_consecutive: None,
+            })
+            .collect();
+        let races = generate(&plan(4, Seeding::Ranking, 1), &pilots(8), &standings);
+        let pilot_ids: Vec<Vec<i64>> = races
+            .iter()
+            .map(|race| race.heats.iter().map(|heat| heat.pilot_id).collect())
+            .collect();
+
+        assert_eq!(pilot_ids, vec![vec![1, 2, 3, 4], vec![5, 6, 7, 8]]);
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DetectionSettings {
+    pub enter_threshold: u16,
+    pub exit_threshold: u16,
+    pub min_lap_time: u64,
+}
+
+impl Default for DetectionSettings {
+    fn default() -> Self {
+        DetectionSettings {
+            enter_threshold: 100,
+            exit_threshold: 90,
+            min_lap_time: 3000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LapCrossing {
+    pub node: u8,
+    pub timestamp: u64,
+    pub peak_rssi: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Peak {
+    rssi: u16,
+    first_timestamp: u64,
+    last_timestamp: u64,
+}
+
+// Nodes stream about 20 samples a second even between races, only the last 15 minutes are kept for the
+// heat's RSSI trace.
+const MAX_SAMPLES: usize = 20 * 60 * 15;
+
+#[derive(Debug, Clone)]
+pub struct NodeDetector {
+    node: u8,
+    settings: DetectionSettings,
+    peak: Option<Peak>,
+    last_crossing: Option<u64>,
+    samples: VecDeque<(u64, u16)>,
+}
+
+impl NodeDetector {
+    pub fn new(node: u8, settings: DetectionSettings) -> NodeDetector {
+        NodeDetector {
+            node,
+            settings,
+            peak: None,
+            last_crossing: None,
+            samples: VecDeque::new(),
+        }
+    }
+
+    // The drone is "in the gate" from the moment RSSI reaches the enter threshold until it drops
+    // below the exit threshold; the crossing is the middle of the highest plateau within that pass.
+    pub fn feed(&mut self, timestamp: u64, rssi: u16) -> Option<LapCrossing> {
+        let previous_timestamp = self.samples.back().map(|(timestamp, _)| *timestamp);
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((timestamp, rssi));
+
+        match self.peak.as_mut() {
+            None => {
+                if rssi >= self.settings.enter_threshold {
+                    self.peak = Some(Peak {
+                        rssi,
+                        first_timestamp: timestamp,
+                        last_timestamp: timestamp,
+                    });
+                }
+                None
+            }
+            Some(peak) => {
+                if rssi > peak.rssi {
+                    *peak = Peak {
+                        rssi,
+                        first_timestamp: timestamp,
+                        last_timestamp: timestamp,
+                    };
+                } else if rssi == peak.rssi && Some(peak.last_timestamp) == previous_timestamp {
+                    peak.last_timestamp = timestamp;
+                }
+
+                if rssi < self.settings.exit_threshold {
+                    let peak = self.peak.take()?;
+                    let crossing_timestamp = peak.first_timestamp + (peak.last_timestamp - peak.first_timestamp) / 2;
+
+                    if let Some(last_crossing) = self.last_crossing {
+                        if crossing_timestamp < last_crossing + self.settings.min_lap_time {
+                            return None;
+                        }
+                    }
+
+                    self.last_crossing = Some(crossing_timestamp);
+
+                    Some(LapCrossing {
+                        node: self.node,
+                        timestamp: crossing_timestamp,
+                        peak_rssi: peak.rssi,
+                    })
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    pub fn set_settings(&mut self, settings: DetectionSettings) {
+        self.settings = settings;
+    }
+
+    pub fn rssi_raw(&self) -> String {
+        self.samples
+            .iter()
+            .map(|(timestamp, rssi)| format!("{}:{}", timestamp, rssi))
+            .collect::<Vec<String>>()
+            .join(";")
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LapDetector {
+    settings: HashMap<u8, DetectionSettings>,
+    nodes: HashMap<u8, NodeDetector>,
+}
+
+impl LapDetector {
+    pub fn feed(&mut self, node: u8, timestamp: u64, rssi: u16) -> Option<LapCrossing> {
+        let settings = self.settings_for(node);
+
+        self.nodes
+            .entry(node)
+            .or_insert_with(|| NodeDetector::new(node, settings))
+            .feed(timestamp, rssi)
+    }
+
+    pub fn settings_for(&self, node: u8) -> DetectionSettings {
+        self.settings.get(&node).copied().unwrap_or_default()
+    }
+
+    pub fn set_settings(&mut self, node: u8, settings: DetectionSettings) {
+        self.settings.insert(node, settings);
+
+        if let Some(detector) = self.nodes.get_mut(&node) {
+            detector.set_settings(settings);
+        }
+    }
+
+    pub fn rssi_raw(&self, node: u8) -> String {
+        self.nodes
+            .get(&node)
+            .map(|detector| detector.rssi_raw())
+            .unwrap_or_default()
+    }
+
+    pub fn reset(&mut self) {
+        self.nodes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_all(detector: &mut NodeDetector, samples: &[(u64, u16)]) -> Vec<LapCrossing> {
+        samples
+            .iter()
+            .filter_map(|(timestamp, rssi)| detector.feed(*timestamp, *rssi))
+            .collect()
+    }
+
+    #[test]
+    fn crosses_once_between_enter_and_exit() {
+        let mut detector = NodeDetector::new(1, DetectionSettings::default());
+        let crossings = feed_all(&mut detector, &[(0, 50), (100, 100), (200, 120), (300, 95), (400, 105), (500, 85)]);
+
+        assert_eq!(crossings, vec![LapCrossing { node: 1, timestamp: 200, peak_rssi: 120 }]);
+    }
+
+    #[test]
+    fn stays_in_the_gate_above_the_exit_threshold() {
+        let mut detector = NodeDetector::new(1, DetectionSettings::default());
+
+        assert_eq!(feed_all(&mut detector, &[(0, 95), (100, 99)]), vec![]);
+        assert_eq!(feed_all(&mut detector, &[(200, 110), (300, 90), (400, 95)]), vec![]);
+        assert_eq!(feed_all(&mut detector, &[(500, 89)]).len(), 1);
+    }
+
+    #[test]
+    fn crosses_in_the_middle_of_the_plateau() {
+        let mut detector = NodeDetector::new(1, DetectionSettings::default());
+        let crossings = feed_all(&mut detector, &[(0, 100), (100, 130), (200, 130), (300, 130), (400, 130), (500, 80)]);
+
+        assert_eq!(crossings[0].timestamp, 250);
+    }
+
+    #[test]
+    fn ignores_a_peak_that_is_not_contiguous() {
+        let mut detector = NodeDetector::new(1, DetectionSettings::default());
+        let crossings = feed_all(&mut detector, &[(0, 130), (100, 120), (200, 130), (300, 80)]);
+
+        assert_eq!(crossings[0].timestamp, 0);
+    }
+
+    #[test]
+    fn suppresses_crossings_within_the_min_lap_time() {
+        let mut detector = NodeDetector::new(1, DetectionSettings { min_lap_time: 3000, ..Default::default() });
+        let crossings = feed_all(&mut detector, &[
+            (0, 120), (100, 50),
+            (2000, 120), (2100, 50),
+            (3000, 120), (3100, 50),
+        ]);
+
+        assert_eq!(crossings.iter().map(|crossing| crossing.timestamp).collect::<Vec<u64>>(), vec![0, 3000]);
+    }
+
+    #[test]
+    fn keeps_settings_per_node() {
+        let mut detector = LapDetector::default();
+        detector.set_settings(2, DetectionSettings { enter_threshold: 200, exit_threshold: 150, min_lap_time: 0 });
+
+        assert_eq!(detector.feed(1, 0, 120), None);
+        assert_eq!(detector.feed(2, 0, 120), None);
+        assert!(detector.feed(1, 100, 50).is_some());
+        assert_eq!(detector.feed(2, 100, 50), None);
+        assert_eq!(detector.rssi_raw(2), "0:120;100:50");
+    }
+}
@@ -0,0 +1,166 @@
+use std::cmp::Ordering;
+use crate::core::{Heat, Lap};
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub enum Gap {
+    Time(i64),
+    Laps(u32),
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LeaderboardEntry {
+    pub position: u32,
+    pub heat_id: i64,
+    pub pilot_id: i64,
+    pub node: u8,
+    pub laps: u32,
+    pub total_time: i64,
+    pub last_lap: Option<i64>,
+    pub best_lap: Option<i64>,
+    pub gap_to_leader: Option<Gap>,
+    pub gap_to_next: Option<Gap>,
+}
+
+impl LeaderboardEntry {
+    fn from_heat(heat: &Heat) -> LeaderboardEntry {
+        let laps: Vec<_> = heat.laps.iter().filter(|lap| lap.is_full_lap()).collect();
+
+        LeaderboardEntry {
+            position: 0,
+            heat_id: heat.id,
+            pilot_id: heat.pilot_id,
+            node: heat.no,
+            laps: laps.len() as u32,
+            total_time: laps.last().map_or(0, |lap| lap.crossed_at),
+            last_lap: laps.last().map(|lap| lap.lap_time),
+            best_lap: laps.iter().map(|lap| lap.lap_time).min(),
+            gap_to_leader: None,
+            gap_to_next: None,
+        }
+    }
+
+    // More laps first, then whoever completed them earlier. Pilots without laps keep the heat order.
+    fn compare(&self, other: &LeaderboardEntry) -> Ordering {
+        other.laps
+            .cmp(&self.laps)
+            .then(self.total_time.cmp(&other.total_time))
+            .then(self.node.cmp(&other.node))
+    }
+
+    fn gap_to(&self, ahead: &LeaderboardEntry) -> Option<Gap> {
+        if self.laps == 0 {
+            None
+        } else if self.laps < ahead.laps {
+            Some(Gap::Laps(ahead.laps - self.laps))
+        } else {
+            Some(Gap::Time(self.total_time - ahead.total_time))
+        }
+    }
+}
+
+pub fn compute(heats: &[Heat]) -> Vec<LeaderboardEntry> {
+    let mut entries: Vec<LeaderboardEntry> = heats.iter().map(LeaderboardEntry::from_heat).collect();
+    entries.sort_by(|a, b| a.compare(b));
+
+    for index in 0..entries.len() {
+        entries[index].position = index as u32 + 1;
+
+        if index > 0 {
+            entries[index].gap_to_leader = entries[index].gap_to(&entries[0]);
+            entries[index].gap_to_next = entries[index].gap_to(&entries[index - 1]);
+        }
+    }
+
+    entries
+}
+
+pub fn best_consecutive(laps: &[Lap], count: u32) -> Option<i64> {
+    if count == 0 {
+        return None;
+    }
+
+    let lap_times: Vec<i64> = laps.iter().filter(|lap| lap.is_full_lap()).map(|lap| lap.lap_time).collect();
+
+    lap_times
+        .windows(count as usize)
+        .map(|window| window.iter().sum())
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::LapSource;
+
+    fn heat(id: i64, pilot_id: i64, lap_times: &[i64]) -> Heat {
+        let mut crossed_at = 1000;
+        let mut laps = vec![Lap::new(id * 100, id, 0, crossed_at, crossed_at, LapSource::Device, false)];
+
+        for (index, lap_time) in lap_times.iter().enumerate() {
+            crossed_at += lap_time;
+            laps.push(Lap::new(id * 100 + index as i64 + 1, id, index as u32 + 1, crossed_at, *lap_time, LapSource::Device, false));
+        }
+
+        Heat::new(id, id as u8, format!("R{}", id).parse().unwrap(), pilot_id, laps)
+    }
+
+    #[test]
+    fn orders_by_laps_then_total_time() {
+        let entries = compute(&[
+            heat(1, 10, &[20_000]),
+            heat(2, 11, &[19_000, 21_000]),
+            heat(3, 12, &[18_000, 21_000]),
+            heat(4, 13, &[]),
+        ]);
+
+        assert_eq!(entries.iter().map(|entry| entry.pilot_id).collect::<Vec<i64>>(), vec![12, 11, 10, 13]);
+        assert_eq!(entries.iter().map(|entry| entry.position).collect::<Vec<u32>>(), vec![1, 2, 3, 4]);
+        assert_eq!(entries[0].best_lap, Some(18_000));
+        assert_eq!(entries[0].last_lap, Some(21_000));
+        assert_eq!(entries[0].total_time, 40_000);
+    }
+
+    #[test]
+    fn does_not_count_the_holeshot_or_deleted_laps() {
+        let mut heat = heat(1, 10, &[20_000, 22_000]);
+        heat.laps[2].deleted = true;
+        let entries = compute(&[heat]);
+
+        assert_eq!(entries[0].laps, 1);
+        assert_eq!(entries[0].best_lap, Some(20_000));
+        assert_eq!(entries[0].total_time, 21_000);
+    }
+
+    #[test]
+    fn keeps_the_heat_order_without_laps() {
+        let entries = compute(&[heat(2, 11, &[]), heat(1, 10, &[])]);
+
+        assert_eq!(entries.iter().map(|entry| entry.node).collect::<Vec<u8>>(), vec![1, 2]);
+        assert_eq!(entries[1].gap_to_leader, None);
+    }
+
+    #[test]
+    fn gaps_in_laps_down_or_time() {
+        let entries = compute(&[
+            heat(1, 10, &[18_000, 20_000]),
+            heat(2, 11, &[19_000, 20_000]),
+            heat(3, 12, &[21_000]),
+        ]);
+
+        assert_eq!(entries[0].gap_to_leader, None);
+        assert_eq!(entries[1].gap_to_leader, Some(Gap::Time(1_000)));
+        assert_eq!(entries[1].gap_to_next, Some(Gap::Time(1_000)));
+        assert_eq!(entries[2].gap_to_leader, Some(Gap::Laps(1)));
+        assert_eq!(entries[2].gap_to_next, Some(Gap::Laps(1)));
+    }
+
+    #[test]
+    fn best_consecutive_needs_a_full_window() {
+        let heat = heat(1, 10, &[20_000, 18_000, 19_000, 25_000]);
+
+        assert_eq!(best_consecutive(&heat.laps, 2), Some(37_000));
+        assert_eq!(best_consecutive(&heat.laps, 4), Some(82_000));
+        assert_eq!(best_consecutive(&heat.laps, 5), None);
+        assert_eq!(best_consecutive(&heat.laps, 0), None);
+    }
+}
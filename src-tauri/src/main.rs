@@ -1,9 +1,23 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod bracket;
+mod channels;
 mod core;
 mod db;
 mod device;
+mod events;
+mod frequency_assignment;
+mod heat_generator;
+mod lap_detection;
+mod leaderboard;
+mod migrations;
+mod protocol;
+mod race_engine;
+mod simulator;
+mod standings;
+mod start_sequence;
+mod storage;
 
 use std::fmt::format;
 use crate::core::{ErrorMessage, InvokeRequest, RaceEventDetailsDto};
@@ -13,11 +27,12 @@ use std::ops::Add;
 use std::sync::Arc;
 use std::time::Duration;
 use serialport::SerialPortType::UsbPort;
+use tauri::api::dialog::{MessageDialogBuilder, MessageDialogKind};
 use tauri::{Manager, Window};
 use tokio::select;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::{mpsc, Mutex};
-use crate::db::Db;
+use crate::db::{Db, DbError};
 
 struct LocalState {
     dispatch: Arc<Mutex<Sender<core::Actions>>>,
@@ -29,10 +44,9 @@ async fn init(state: tauri::State<'_, LocalState>) -> Result<core::State, ErrorM
     let mut lock = state.dispatch.lock().await;
     lock.send(core::Actions::Init(request))
         .await
-        .map_err(|e1| e1.to_string())
-        .unwrap();
+        .map_err(|_| ErrorMessage::core_stopped())?;
 
-    receiver.await.unwrap()
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
 }
 
 #[tauri::command]
@@ -44,10 +58,9 @@ async fn create_race_event(
     let mut lock = state.dispatch.lock().await;
     lock.send(core::Actions::CreateRaceEvent(request))
         .await
-        .map_err(|e1| e1.to_string())
-        .unwrap();
+        .map_err(|_| ErrorMessage::core_stopped())?;
 
-    receiver.await.unwrap()
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
 }
 
 #[tauri::command]
@@ -59,10 +72,9 @@ async fn set_pilot(
     let mut lock = state.dispatch.lock().await;
     lock.send(core::Actions::AddPilot(request))
         .await
-        .map_err(|e1| e1.to_string())
-        .unwrap();
+        .map_err(|_| ErrorMessage::core_stopped())?;
 
-    receiver.await.unwrap()
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
 }
 
 #[tauri::command]
@@ -74,10 +86,9 @@ async fn add_race(
     let mut lock = state.dispatch.lock().await;
     lock.send(core::Actions::AddRace(request))
         .await
-        .map_err(|e1| e1.to_string())
-        .unwrap();
+        .map_err(|_| ErrorMessage::core_stopped())?;
 
-    receiver.await.unwrap()
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
 }
 
 #[tauri::command]
@@ -89,10 +100,9 @@ async fn remove_race_event(
     let mut lock = state.dispatch.lock().await;
     lock.send(core::Actions::RemoveRaceEvent(request))
         .await
-        .map_err(|e| e.to_string())
-        .unwrap();
+        .map_err(|_| ErrorMessage::core_stopped())?;
 
-    receiver.await.unwrap()
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
 }
 
 #[tauri::command]
@@ -104,31 +114,557 @@ async fn find_race_event_details(
     let mut lock = state.dispatch.lock().await;
     lock.send(core::Actions::LoadRaceEvent(request))
         .await
-        .map_err(|e| e.to_string())
-        .unwrap();
+        .map_err(|_| ErrorMessage::core_stopped())?;
 
-    receiver.await.unwrap()
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
 }
 
 #[tauri::command]
 async fn start_race(
+    race_ref_dto: core::RaceRefDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<core::Race, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(race_ref_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::StartRace(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn stop_race(
+    race_ref_dto: core::RaceRefDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<core::Race, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(race_ref_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::StopRace(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn interrupt_race(
+    race_ref_dto: core::RaceRefDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<core::Race, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(race_ref_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::InterruptRace(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn resume_race(
+    race_ref_dto: core::RaceRefDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<core::Race, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(race_ref_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::ResumeRace(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn finish_race(
+    race_ref_dto: core::RaceRefDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<core::Race, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(race_ref_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::FinishRace(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn send_device_command(
+    command: device::Commands,
+    state: tauri::State<'_, LocalState>
+) -> Result<protocol::DeviceEvent, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(command);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::SendCommand(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn set_detection_settings(
+    node_detection_settings_dto: core::NodeDetectionSettingsDto,
     state: tauri::State<'_, LocalState>
 ) -> Result<(), ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(node_detection_settings_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::SetDetectionSettings(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn set_start_sequence_settings(
+    start_sequence_settings: start_sequence::StartSequenceSettings,
+    state: tauri::State<'_, LocalState>
+) -> Result<(), ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(start_sequence_settings);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::SetStartSequenceSettings(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn list_devices(
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<device::PortInfo>, ErrorMessage> {
     let (request, receiver) = InvokeRequest::new(());
     let mut lock = state.dispatch.lock().await;
-    lock.send(core::Actions::StartRace(request))
+    lock.send(core::Actions::ListDevices(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn connect_device(
+    device_selection: device::DeviceSelection,
+    state: tauri::State<'_, LocalState>
+) -> Result<device::ConnectionStatus, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(device_selection);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::ConnectDevice(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn disconnect_device(
+    state: tauri::State<'_, LocalState>
+) -> Result<device::ConnectionStatus, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(());
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::DisconnectDevice(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn get_device_status(
+    state: tauri::State<'_, LocalState>
+) -> Result<device::ConnectionStatus, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(());
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::GetDeviceStatus(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn get_leaderboard(
+    race_ref_dto: core::RaceRefDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<leaderboard::LeaderboardEntry>, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(race_ref_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::GetLeaderboard(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn find_event_standings(
+    race_filter_dto: core::RaceFilterDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<core::EventStandingsDto, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(race_filter_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::GetEventStandings(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn set_points_settings(
+    points_settings_dto: core::PointsSettingsDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<(), ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(points_settings_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::SetPointsSettings(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn find_consecutive_ranking(
+    consecutive_ranking_dto: core::ConsecutiveRankingDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<standings::ConsecutiveRanking>, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(consecutive_ranking_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::GetConsecutiveRanking(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn generate_heats(
+    heat_plan_dto: heat_generator::HeatPlanDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<core::Race>, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(heat_plan_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::GenerateHeats(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn create_bracket(
+    new_bracket_dto: bracket::NewBracketDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<bracket::Bracket, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(new_bracket_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::CreateBracket(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn find_brackets(
+    race_event_id: i64,
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<bracket::Bracket>, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(race_event_id);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::GetBrackets(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn add_class(
+    new_class_dto: core::NewClassDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<core::Class, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(new_class_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::AddClass(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn set_class_pilots(
+    class_pilots_dto: core::ClassPilotsDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<core::Class, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(class_pilots_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::SetClassPilots(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn add_round(
+    new_round_dto: core::NewRoundDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<core::Round, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(new_round_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::AddRound(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn find_races(
+    race_filter_dto: core::RaceFilterDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<core::Race>, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(race_filter_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::FindRaces(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn check_channels(
+    channels: Vec<channels::Channel>,
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<channels::InterferenceIssue>, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(channels);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::CheckChannels(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn set_pilot_video_system(
+    pilot_video_system_dto: core::PilotVideoSystemDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<core::Pilot, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(pilot_video_system_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::SetPilotVideoSystem(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn assign_channels(
+    race_ref_dto: core::RaceRefDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<core::Race, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(race_ref_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::AssignChannels(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn add_pilot_profile(
+    new_pilot_profile_dto: core::NewPilotProfileDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<core::PilotProfile, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(new_pilot_profile_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::AddPilotProfile(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn update_pilot_profile(
+    pilot_profile: core::PilotProfile,
+    state: tauri::State<'_, LocalState>
+) -> Result<core::PilotProfile, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(pilot_profile);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::UpdatePilotProfile(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn find_pilot_profiles(
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<core::PilotProfile>, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(());
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::FindPilotProfiles(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn import_pilots(
+    race_event_id: i64,
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<core::PilotProfile>, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(race_event_id);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::ImportPilots(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn add_lap(
+    new_lap_dto: core::NewLapDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<core::Lap>, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(new_lap_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::AddLap(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn delete_lap(
+    lap_ref_dto: core::LapRefDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<core::Lap>, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(lap_ref_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::DeleteLap(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn restore_lap(
+    lap_ref_dto: core::LapRefDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<core::Lap>, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(lap_ref_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::RestoreLap(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn split_lap(
+    split_lap_dto: core::SplitLapDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<core::Lap>, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(split_lap_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::SplitLap(request))
         .await
-        .map_err(|e| e.to_string())
-        .unwrap();
+        .map_err(|_| ErrorMessage::core_stopped())?;
 
-    receiver.await.unwrap()
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn merge_laps(
+    lap_ref_dto: core::LapRefDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<core::Lap>, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(lap_ref_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::MergeLaps(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn find_audit_log(
+    audit_log_filter_dto: core::AuditLogFilterDto,
+    state: tauri::State<'_, LocalState>
+) -> Result<Vec<core::AuditEntry>, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(audit_log_filter_dto);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::FindAuditLog(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn get_operator(
+    state: tauri::State<'_, LocalState>
+) -> Result<String, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(());
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::GetOperator(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
+}
+
+#[tauri::command]
+async fn set_operator(
+    operator: String,
+    state: tauri::State<'_, LocalState>
+) -> Result<String, ErrorMessage> {
+    let (request, receiver) = InvokeRequest::new(operator);
+    let mut lock = state.dispatch.lock().await;
+    lock.send(core::Actions::SetOperator(request))
+        .await
+        .map_err(|_| ErrorMessage::core_stopped())?;
+
+    receiver.await.map_err(|_| ErrorMessage::core_stopped())?
 }
 
 fn main() {
-    let mut state = core::State::init(Db::init());
+    let context = tauri::generate_context!();
+    let args: Vec<String> = std::env::args().collect();
+    // A database that can not be opened is reported once the app is up, instead of crashing at start.
+    let startup = storage::init(storage::resolve_data_root(&args, tauri::api::path::app_data_dir(context.config())))
+        .and_then(|_| {
+            if let Ok(working_dir) = std::env::current_dir() {
+                storage::migrate_legacy_files(&working_dir);
+            }
+            Db::init()
+        })
+        .and_then(|race_events| Ok((core::State::init(race_events), Db::main()?)));
+    let device_selection = device::DeviceSelection::from_env()
+        .or_else(|| startup.as_ref().ok().and_then(|(_, db)| db.find_device_selection().ok().flatten()));
 
     let (dispatch, listener) = mpsc::channel(5);
     let (device_tx, device_rx) = mpsc::channel(5);
+    let (device_control_tx, device_control_rx) = mpsc::channel(5);
+    let (device_events_tx, device_events_rx) = mpsc::channel(100);
 
     let token = tokio_util::sync::CancellationToken::new();
     let cloned_token = token.clone();
@@ -142,6 +678,43 @@ fn main() {
             remove_race_event,
             find_race_event_details,
             start_race,
+            stop_race,
+            interrupt_race,
+            resume_race,
+            finish_race,
+            send_device_command,
+            set_detection_settings,
+            set_start_sequence_settings,
+            list_devices,
+            connect_device,
+            disconnect_device,
+            get_device_status,
+            get_leaderboard,
+            find_event_standings,
+            set_points_settings,
+            find_consecutive_ranking,
+            generate_heats,
+            create_bracket,
+            find_brackets,
+            add_class,
+            set_class_pilots,
+            add_round,
+            find_races,
+            check_channels,
+            set_pilot_video_system,
+            assign_channels,
+            add_pilot_profile,
+            update_pilot_profile,
+            find_pilot_profiles,
+            import_pilots,
+            add_lap,
+            delete_lap,
+            restore_lap,
+            split_lap,
+            merge_laps,
+            find_audit_log,
+            get_operator,
+            set_operator,
         ])
         .manage(LocalState {
             dispatch: Arc::new(Mutex::new(dispatch.clone())),
@@ -154,22 +727,40 @@ fn main() {
                 window.close_devtools();
             }
 
-            // let app_handle = app.handle();
+            let app_handle = app.handle();
+            let device_app_handle = app.handle();
+
+            let (mut state, db) = match startup {
+                Ok(startup) => startup,
+                Err(error) => {
+                    println!("Can not open the database: {}", error);
+                    let message = match error {
+                        DbError::Busy => "The database is used by another program. Close it and start patlimer again.".to_string(),
+                        error => error.to_string(),
+                    };
+
+                    MessageDialogBuilder::new("Can not open the database", message)
+                        .kind(MessageDialogKind::Error)
+                        .show(move |_| app_handle.exit(1));
+
+                    return Ok(());
+                }
+            };
 
             tauri::async_runtime::spawn(async move {
-                core::update_state(&mut state, listener, device_tx).await;
+                core::update_state(&mut state, db, listener, device_tx, device_control_tx, device_events_rx, app_handle).await;
             });
 
             tauri::async_runtime::spawn(async move {
                 select! {
                     _ = cloned_token.cancelled() => {}
-                    _ = device::process_data(device_rx) => {}
+                    _ = device::process_data(device_rx, device_control_rx, device_events_tx, device_app_handle, device_selection) => {}
                 }
             });
 
             Ok(())
         })
-        .build(tauri::generate_context!())
+        .build(context)
         .expect("error while running tauri application")
         .run(move |_app_handle, event| match event {
             tauri::RunEvent::Exit => {
@@ -0,0 +1,365 @@
+use rusqlite::{Connection, Transaction};
+use crate::db::DbError;
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub apply: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+pub fn latest_version(migrations: &[Migration]) -> u32 {
+    migrations.last().map_or(0, |migration| migration.version)
+}
+
+// Applies every migration newer than the file's `user_version`, each one in its own transaction together with
+// the version bump, and refuses files written by a newer version of the app. Returns the applied versions.
+pub fn migrate<'a>(connection: &mut Connection, migrations: &'a [Migration]) -> Result<Vec<&'a Migration>, DbError> {
+    let found: u32 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let supported = latest_version(migrations);
+
+    if found > supported {
+        return Err(DbError::NewerVersion { found, supported });
+    }
+
+    let mut applied = Vec::new();
+
+    for migration in migrations.iter().filter(|migration| migration.version > found) {
+        let tx = connection.transaction()?;
+        (migration.apply)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+
+        applied.push(migration);
+    }
+
+    Ok(applied)
+}
+
+// Files created before versioning are at version 0 whatever tables they already have, so migrations only
+// add what is missing.
+fn add_column_if_missing(tx: &Transaction, table: &str, column: &str, definition: &str) -> rusqlite::Result<()> {
+    let mut statement = tx.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = statement
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<String>>>()?
+        .iter()
+        .any(|name| name == column);
+
+    if !exists {
+        tx.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), ())?;
+    }
+
+    Ok(())
+}
+
+pub const MAIN_MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, description: "Race events", apply: main_race_events },
+    Migration { version: 2, description: "Settings", apply: main_settings },
+    Migration { version: 3, description: "Pilot profiles", apply: main_pilot_profiles },
+];
+
+fn main_race_events(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute("CREATE TABLE IF NOT EXISTS raceEvents (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        race_event_type TEXT NOT NULL
+    )", ())?;
+
+    Ok(())
+}
+
+fn main_settings(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute("CREATE TABLE IF NOT EXISTS settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    )", ())?;
+
+    Ok(())
+}
+
+fn main_pilot_profiles(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute("CREATE TABLE IF NOT EXISTS pilot_profiles (
+        id INTEGER PRIMARY KEY,
+        callsign TEXT NOT NULL UNIQUE COLLATE NOCASE,
+        real_name TEXT,
+        country TEXT,
+        team TEXT,
+        video_system TEXT NOT NULL DEFAULT 'Analog',
+        preferred_channel TEXT,
+        transponder TEXT,
+        phonetic_name TEXT,
+        avatar_path TEXT
+    )", ())?;
+
+    Ok(())
+}
+
+pub const EVENT_MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, description: "Pilots, races and heats", apply: event_races },
+    Migration { version: 2, description: "Laps", apply: event_laps },
+    Migration { version: 3, description: "Race formats and timestamps", apply: event_race_formats },
+    Migration { version: 4, description: "Settings", apply: event_settings },
+    Migration { version: 5, description: "Brackets", apply: event_brackets },
+    Migration { version: 6, description: "Classes and rounds", apply: event_classes },
+    Migration { version: 7, description: "Pilot video systems", apply: event_pilot_video_systems },
+    Migration { version: 8, description: "Pilot profiles", apply: event_pilot_profiles },
+    Migration { version: 9, description: "Audit log", apply: event_audit_log },
+    Migration { version: 10, description: "Holeshot as lap 0", apply: event_holeshot_laps },
+];
+
+fn event_races(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute("CREATE TABLE IF NOT EXISTS pilots (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL
+    )", ())?;
+
+    tx.execute("CREATE TABLE IF NOT EXISTS races (
+        id INTEGER PRIMARY KEY,
+        name INTEGER NOT NULL,
+        status TEXT NOT NULL
+    )", ())?;
+
+    tx.execute("CREATE TABLE IF NOT EXISTS heats (
+        id INTEGER PRIMARY KEY,
+        no INTEGER NOT NULL,
+        channel TEXT NOT NULL,
+        pilot_id INTEGER NOT NULL,
+        race_id INTEGER NOT NULL,
+        rssi_raw TEXT NOT NULL,
+        FOREIGN KEY(pilot_id) REFERENCES pilots(id),
+        FOREIGN KEY(race_id) REFERENCES races(id)
+    )", ())?;
+
+    Ok(())
+}
+
+fn event_laps(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute("CREATE TABLE IF NOT EXISTS laps (
+        id INTEGER PRIMARY KEY,
+        heat_id INTEGER NOT NULL,
+        no INTEGER NOT NULL,
+        crossed_at INTEGER NOT NULL,
+        lap_time INTEGER NOT NULL,
+        source TEXT NOT NULL,
+        deleted INTEGER NOT NULL DEFAULT 0,
+        FOREIGN KEY(heat_id) REFERENCES heats(id)
+    )", ())?;
+
+    Ok(())
+}
+
+fn event_race_formats(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "races", "format", "TEXT NOT NULL DEFAULT '{\"type\":\"Manual\"}'")?;
+    add_column_if_missing(tx, "races", "started_at", "TEXT")?;
+    add_column_if_missing(tx, "races", "finished_at", "TEXT")
+}
+
+fn event_settings(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute("CREATE TABLE IF NOT EXISTS settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    )", ())?;
+
+    Ok(())
+}
+
+fn event_brackets(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute("CREATE TABLE IF NOT EXISTS brackets (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        bracket_type TEXT NOT NULL,
+        channels TEXT NOT NULL
+    )", ())?;
+
+    tx.execute("CREATE TABLE IF NOT EXISTS bracket_races (
+        race_id INTEGER PRIMARY KEY,
+        bracket_id INTEGER NOT NULL,
+        side TEXT NOT NULL,
+        round INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        FOREIGN KEY(race_id) REFERENCES races(id),
+        FOREIGN KEY(bracket_id) REFERENCES brackets(id)
+    )", ())?;
+
+    tx.execute("CREATE TABLE IF NOT EXISTS bracket_feeds (
+        id INTEGER PRIMARY KEY,
+        race_id INTEGER NOT NULL,
+        slot INTEGER NOT NULL,
+        source_race_id INTEGER NOT NULL,
+        source_place INTEGER NOT NULL,
+        FOREIGN KEY(race_id) REFERENCES races(id),
+        FOREIGN KEY(source_race_id) REFERENCES races(id)
+    )", ())?;
+
+    Ok(())
+}
+
+fn event_classes(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute("CREATE TABLE IF NOT EXISTS classes (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL
+    )", ())?;
+
+    tx.execute("CREATE TABLE IF NOT EXISTS class_pilots (
+        class_id INTEGER NOT NULL,
+        pilot_id INTEGER NOT NULL,
+        PRIMARY KEY(class_id, pilot_id),
+        FOREIGN KEY(class_id) REFERENCES classes(id),
+        FOREIGN KEY(pilot_id) REFERENCES pilots(id)
+    )", ())?;
+
+    tx.execute("CREATE TABLE IF NOT EXISTS rounds (
+        id INTEGER PRIMARY KEY,
+        class_id INTEGER NOT NULL,
+        no INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        round_type TEXT NOT NULL,
+        FOREIGN KEY(class_id) REFERENCES classes(id)
+    )", ())?;
+
+    add_column_if_missing(tx, "races", "round_id", "INTEGER REFERENCES rounds(id)")
+}
+
+fn event_pilot_video_systems(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "pilots", "video_system", "TEXT NOT NULL DEFAULT 'Analog'")
+}
+
+fn event_pilot_profiles(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column_if_missing(tx, "pilots", "profile_id", "INTEGER")?;
+    add_column_if_missing(tx, "pilots", "preferred_channel", "TEXT")
+}
+
+fn event_audit_log(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute("CREATE TABLE IF NOT EXISTS audit_log (
+        id INTEGER PRIMARY KEY,
+        created_at TEXT NOT NULL,
+        actor TEXT NOT NULL,
+        action TEXT NOT NULL,
+        race_id INTEGER,
+        subject TEXT,
+        before TEXT,
+        after TEXT
+    )", ())?;
+
+    tx.execute("CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+    BEGIN
+        SELECT RAISE(ABORT, 'The audit log is append-only');
+    END", ())?;
+
+    tx.execute("CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+    BEGIN
+        SELECT RAISE(ABORT, 'The audit log is append-only');
+    END", ())?;
+
+    Ok(())
+}
+
+// Laps used to be numbered from 1 with the holeshot as lap 1, deleted laps are renumbered when restored.
+fn event_holeshot_laps(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute("UPDATE laps SET no = no - 1 WHERE deleted = 0 AND no > 0", ())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_version(connection: &Connection) -> u32 {
+        connection.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap()
+    }
+
+    fn columns(connection: &Connection, table: &str) -> Vec<String> {
+        let mut statement = connection.prepare(&format!("PRAGMA table_info({})", table)).unwrap();
+        let columns = statement.query_map([], |row| row.get(1)).unwrap().collect::<rusqlite::Result<_>>().unwrap();
+
+        columns
+    }
+
+    // An event file as written before the schema was versioned.
+    fn baseline_event() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch("
+            CREATE TABLE pilots (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+            CREATE TABLE races (id INTEGER PRIMARY KEY, name INTEGER NOT NULL, status TEXT NOT NULL);
+            CREATE TABLE heats (id INTEGER PRIMARY KEY, no INTEGER NOT NULL, channel TEXT NOT NULL, pilot_id INTEGER NOT NULL, race_id INTEGER NOT NULL, rssi_raw TEXT NOT NULL);
+            INSERT INTO pilots (name) VALUES ('Old pilot');
+            INSERT INTO races (name, status) VALUES ('Race 1', 'Finished');
+        ").unwrap();
+
+        connection
+    }
+
+    #[test]
+    fn migrates_a_baseline_event() {
+        let mut connection = baseline_event();
+
+        let applied = migrate(&mut connection, EVENT_MIGRATIONS).unwrap();
+
+        assert_eq!(
+            applied.iter().map(|migration| migration.version).collect::<Vec<_>>(),
+            EVENT_MIGRATIONS.iter().map(|migration| migration.version).collect::<Vec<_>>()
+        );
+        assert_eq!(user_version(&connection), latest_version(EVENT_MIGRATIONS));
+        assert_eq!(columns(&connection, "pilots"), ["id", "name", "video_system", "profile_id", "preferred_channel"]);
+        assert_eq!(columns(&connection, "races"), ["id", "name", "status", "format", "started_at", "finished_at", "round_id"]);
+        for table in ["laps", "settings", "brackets", "bracket_races", "bracket_feeds", "classes", "class_pilots", "rounds", "audit_log"] {
+            assert!(!columns(&connection, table).is_empty(), "missing table {}", table);
+        }
+
+        let (name, format): (String, String) = connection.query_row("SELECT name, format FROM races", [], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert_eq!(name, "Race 1");
+        assert_eq!(format, r#"{"type":"Manual"}"#);
+    }
+
+    #[test]
+    fn rerun_is_a_no_op() {
+        let mut connection = baseline_event();
+        migrate(&mut connection, EVENT_MIGRATIONS).unwrap();
+
+        assert!(migrate(&mut connection, EVENT_MIGRATIONS).unwrap().is_empty());
+        assert_eq!(user_version(&connection), latest_version(EVENT_MIGRATIONS));
+    }
+
+    #[test]
+    fn rejects_a_newer_version() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        let supported = latest_version(EVENT_MIGRATIONS);
+        connection.pragma_update(None, "user_version", supported + 1).unwrap();
+
+        let result = migrate(&mut connection, EVENT_MIGRATIONS);
+
+        assert!(matches!(result, Err(DbError::NewerVersion { found, supported: s }) if found == supported + 1 && s == supported));
+        assert!(columns(&connection, "pilots").is_empty());
+    }
+
+    #[test]
+    fn renumbers_holeshots_to_lap_0() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection, &EVENT_MIGRATIONS[..9]).unwrap();
+        connection.execute_batch("
+            INSERT INTO pilots (name) VALUES ('Pilot');
+            INSERT INTO races (name, status) VALUES ('Race 1', 'Finished');
+            INSERT INTO heats (no, channel, pilot_id, race_id, rssi_raw) VALUES (1, 'R1', 1, 1, '');
+            INSERT INTO laps (heat_id, no, crossed_at, lap_time, source, deleted) VALUES (1, 1, 2000, 2000, 'Device', 0);
+            INSERT INTO laps (heat_id, no, crossed_at, lap_time, source, deleted) VALUES (1, 2, 30000, 28000, 'Device', 0);
+        ").unwrap();
+
+        migrate(&mut connection, EVENT_MIGRATIONS).unwrap();
+
+        let mut statement = connection.prepare("SELECT no FROM laps ORDER BY crossed_at").unwrap();
+        let numbers: Vec<u32> = statement.query_map([], |row| row.get(0)).unwrap().collect::<rusqlite::Result<_>>().unwrap();
+        assert_eq!(numbers, [0, 1]);
+    }
+
+    #[test]
+    fn migrates_a_new_main_file() {
+        let mut connection = Connection::open_in_memory().unwrap();
+
+        migrate(&mut connection, MAIN_MIGRATIONS).unwrap();
+
+        assert_eq!(user_version(&connection), latest_version(MAIN_MIGRATIONS));
+        assert!(columns(&connection, "pilot_profiles").contains(&"callsign".to_string()));
+    }
+}
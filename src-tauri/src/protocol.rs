@@ -0,0 +1,217 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum DeviceEvent {
+    Lap { node: u8, timestamp: u64 },
+    Rssi { node: u8, timestamp: u64, rssi: u16 },
+    Ack { command: String },
+    Version { version: String },
+    Heartbeat { timestamp: u64 },
+    Error { message: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    Empty,
+    UnknownType(String),
+    MissingField(&'static str),
+    InvalidField { field: &'static str, value: String },
+    UnexpectedField(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "Empty line"),
+            ParseError::UnknownType(kind) => write!(f, "Unknown message type '{}'", kind),
+            ParseError::MissingField(field) => write!(f, "Missing '{}' field", field),
+            ParseError::InvalidField { field, value } => {
+                write!(f, "Invalid value '{}' of '{}' field", value, field)
+            }
+            ParseError::UnexpectedField(value) => write!(f, "Unexpected field '{}'", value),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Every line is `<type>:<field>:<field>...`, e.g. `l:1:15230` for a lap on node 1.
+pub fn parse_line(line: &str) -> Result<DeviceEvent, ParseError> {
+    let line = line.trim();
+
+    if line.is_empty() {
+        return Err(ParseError::Empty);
+    }
+
+    let (kind, rest) = match line.split_once(':') {
+        Some((kind, rest)) => (kind, Some(rest)),
+        None => (line, None),
+    };
+
+    match kind {
+        "l" => {
+            let mut fields = Fields::new(rest);
+            let event = DeviceEvent::Lap {
+                node: fields.next("node")?,
+                timestamp: fields.next("timestamp")?,
+            };
+            fields.end()?;
+            Ok(event)
+        }
+        "s" => {
+            let mut fields = Fields::new(rest);
+            let event = DeviceEvent::Rssi {
+                node: fields.next("node")?,
+                timestamp: fields.next("timestamp")?,
+                rssi: fields.next("rssi")?,
+            };
+            fields.end()?;
+            Ok(event)
+        }
+        "h" => {
+            let mut fields = Fields::new(rest);
+            let event = DeviceEvent::Heartbeat {
+                timestamp: fields.next("timestamp")?,
+            };
+            fields.end()?;
+            Ok(event)
+        }
+        "a" => match rest {
+            Some(command) if !command.is_empty() => Ok(DeviceEvent::Ack {
+                command: command.to_string(),
+            }),
+            _ => Err(ParseError::MissingField("command")),
+        },
+        "v" => match rest {
+            Some(version) if !version.is_empty() => Ok(DeviceEvent::Version {
+                version: version.to_string(),
+            }),
+            _ => Err(ParseError::MissingField("version")),
+        },
+        "e" => match rest {
+            Some(message) if !message.is_empty() => Ok(DeviceEvent::Error {
+                message: message.to_string(),
+            }),
+            _ => Err(ParseError::MissingField("message")),
+        },
+        _ => Err(ParseError::UnknownType(kind.to_string())),
+    }
+}
+
+struct Fields<'a> {
+    parts: Option<std::str::Split<'a, char>>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(rest: Option<&'a str>) -> Fields<'a> {
+        Fields {
+            parts: rest.map(|rest| rest.split(':')),
+        }
+    }
+
+    fn next<T: FromStr>(&mut self, field: &'static str) -> Result<T, ParseError> {
+        let value = self
+            .parts
+            .as_mut()
+            .and_then(|parts| parts.next())
+            .filter(|value| !value.is_empty())
+            .ok_or(ParseError::MissingField(field))?;
+
+        value.parse().map_err(|_| ParseError::InvalidField {
+            field,
+            value: value.to_string(),
+        })
+    }
+
+    fn end(&mut self) -> Result<(), ParseError> {
+        match self.parts.as_mut().and_then(|parts| parts.next()) {
+            Some(value) => Err(ParseError::UnexpectedField(value.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lap() {
+        assert_eq!(parse_line("l:1:15230"), Ok(DeviceEvent::Lap { node: 1, timestamp: 15230 }));
+    }
+
+    #[test]
+    fn parses_rssi() {
+        assert_eq!(parse_line("s:2:100:512"), Ok(DeviceEvent::Rssi { node: 2, timestamp: 100, rssi: 512 }));
+    }
+
+    #[test]
+    fn parses_heartbeat() {
+        assert_eq!(parse_line("h:5000"), Ok(DeviceEvent::Heartbeat { timestamp: 5000 }));
+    }
+
+    #[test]
+    fn parses_ack_with_the_whole_command() {
+        assert_eq!(parse_line("a:f:1:5658"), Ok(DeviceEvent::Ack { command: "f:1:5658".to_string() }));
+    }
+
+    #[test]
+    fn parses_version() {
+        assert_eq!(parse_line("v:1.2.0"), Ok(DeviceEvent::Version { version: "1.2.0".to_string() }));
+    }
+
+    #[test]
+    fn parses_error_with_colons() {
+        assert_eq!(parse_line("e:bad:command"), Ok(DeviceEvent::Error { message: "bad:command".to_string() }));
+    }
+
+    #[test]
+    fn trims_line_endings() {
+        assert_eq!(parse_line("l:1:15230\r\n"), Ok(DeviceEvent::Lap { node: 1, timestamp: 15230 }));
+    }
+
+    #[test]
+    fn rejects_empty_lines() {
+        assert_eq!(parse_line(""), Err(ParseError::Empty));
+        assert_eq!(parse_line("  \r\n"), Err(ParseError::Empty));
+    }
+
+    #[test]
+    fn rejects_unknown_types() {
+        assert_eq!(parse_line("x:1"), Err(ParseError::UnknownType("x".to_string())));
+    }
+
+    #[test]
+    fn rejects_missing_fields() {
+        assert_eq!(parse_line("l:1"), Err(ParseError::MissingField("timestamp")));
+        assert_eq!(parse_line("l"), Err(ParseError::MissingField("node")));
+        assert_eq!(parse_line("s:1::300"), Err(ParseError::MissingField("timestamp")));
+        assert_eq!(parse_line("a"), Err(ParseError::MissingField("command")));
+        assert_eq!(parse_line("v:"), Err(ParseError::MissingField("version")));
+        assert_eq!(parse_line("e"), Err(ParseError::MissingField("message")));
+    }
+
+    #[test]
+    fn rejects_malformed_fields() {
+        assert_eq!(
+            parse_line("l:x:15230"),
+            Err(ParseError::InvalidField { field: "node", value: "x".to_string() })
+        );
+        assert_eq!(
+            parse_line("s:1:100:70000"),
+            Err(ParseError::InvalidField { field: "rssi", value: "70000".to_string() })
+        );
+        assert_eq!(
+            parse_line("h:-1"),
+            Err(ParseError::InvalidField { field: "timestamp", value: "-1".to_string() })
+        );
+    }
+
+    #[test]
+    fn rejects_extra_fields() {
+        assert_eq!(parse_line("l:1:15230:9"), Err(ParseError::UnexpectedField("9".to_string())));
+        assert_eq!(parse_line("h:5000:1"), Err(ParseError::UnexpectedField("1".to_string())));
+    }
+}
@@ -0,0 +1,113 @@
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum RaceFormat {
+    Manual,
+    FirstToLaps { laps: u32 },
+    Timed { duration: i64, grace: i64 },
+    FixedTime { duration: i64, max_laps: u32 },
+}
+
+impl Default for RaceFormat {
+    fn default() -> Self {
+        RaceFormat::Manual
+    }
+}
+
+impl FromSql for RaceFormat {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().and_then(|s| {
+            serde_json::from_str(s).map_err(|e| FromSqlError::Other(Box::new(e)))
+        })
+    }
+}
+
+impl ToSql for RaceFormat {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        serde_json::to_string(self)
+            .map(ToSqlOutput::from)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+    }
+}
+
+impl RaceFormat {
+    // `crossings` holds the race times of the valid laps of every heat, `elapsed` is the current race time.
+    pub fn is_finished(&self, crossings: &[Vec<i64>], elapsed: i64) -> bool {
+        match self {
+            RaceFormat::Manual => false,
+            RaceFormat::FirstToLaps { laps } => {
+                crossings.iter().any(|heat| heat.len() >= *laps as usize)
+            }
+            RaceFormat::Timed { duration, grace } => {
+                if elapsed < *duration {
+                    return false;
+                }
+
+                let all_finished = crossings
+                    .iter()
+                    .all(|heat| heat.iter().any(|crossed_at| crossed_at >= duration));
+
+                all_finished || elapsed >= duration + grace
+            }
+            RaceFormat::FixedTime { duration, max_laps } => {
+                let all_finished = !crossings.is_empty()
+                    && crossings.iter().all(|heat| heat.len() >= *max_laps as usize);
+
+                all_finished || elapsed >= *duration
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_races_never_finish() {
+        assert!(!RaceFormat::Manual.is_finished(&[vec![1000, 2000, 3000]], 1_000_000));
+    }
+
+    #[test]
+    fn first_to_laps_finishes_with_the_first_heat_to_reach_the_laps() {
+        let format = RaceFormat::FirstToLaps { laps: 3 };
+
+        assert!(!format.is_finished(&[vec![1000, 2000], vec![1100, 2100]], 2500));
+        assert!(format.is_finished(&[vec![1000, 2000, 3000], vec![1100, 2100]], 3000));
+    }
+
+    #[test]
+    fn timed_waits_for_every_heat_to_cross_after_the_duration() {
+        let format = RaceFormat::Timed { duration: 60_000, grace: 10_000 };
+
+        assert!(!format.is_finished(&[vec![58_000], vec![59_000]], 59_500));
+        assert!(!format.is_finished(&[vec![58_000, 61_000], vec![59_000]], 62_000));
+        assert!(format.is_finished(&[vec![58_000, 61_000], vec![59_000, 63_000]], 63_000));
+    }
+
+    #[test]
+    fn timed_finishes_after_the_grace_period() {
+        let format = RaceFormat::Timed { duration: 60_000, grace: 10_000 };
+
+        assert!(!format.is_finished(&[vec![61_000], vec![59_000]], 69_999));
+        assert!(format.is_finished(&[vec![61_000], vec![59_000]], 70_000));
+    }
+
+    #[test]
+    fn fixed_time_finishes_when_every_heat_reaches_the_max_laps() {
+        let format = RaceFormat::FixedTime { duration: 120_000, max_laps: 2 };
+
+        assert!(!format.is_finished(&[], 30_000));
+        assert!(!format.is_finished(&[vec![10_000, 20_000], vec![11_000]], 30_000));
+        assert!(format.is_finished(&[vec![10_000, 20_000], vec![11_000, 21_000]], 30_000));
+    }
+
+    #[test]
+    fn fixed_time_finishes_at_the_duration() {
+        let format = RaceFormat::FixedTime { duration: 120_000, max_laps: 5 };
+
+        assert!(!format.is_finished(&[vec![10_000]], 119_999));
+        assert!(format.is_finished(&[vec![10_000]], 120_000));
+    }
+}
@@ -0,0 +1,319 @@
+use std::f64::consts::PI;
+use std::io;
+use std::io::{BufReader, Write};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::Rng;
+use crate::device::{Capabilities, ChannelReader, Reader, Transport, Writer};
+
+const BASE_RSSI: f64 = 40.0;
+const PEAK_RSSI: f64 = 110.0;
+const PEAK_WIDTH: f64 = 250.0;
+const RSSI_NOISE: f64 = 5.0;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SimulatedPilot {
+    pub mean_lap_time: u64,
+    pub lap_time_spread: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SimulatorSettings {
+    pub pilots: Vec<SimulatedPilot>,
+    pub rssi_interval: u64,
+    pub stream_rssi: bool,
+    pub report_laps: bool,
+}
+
+impl Default for SimulatorSettings {
+    fn default() -> Self {
+        SimulatorSettings {
+            pilots: [25_000, 27_000, 30_000, 33_000]
+                .iter()
+                .map(|mean_lap_time| SimulatedPilot {
+                    mean_lap_time: *mean_lap_time,
+                    lap_time_spread: 2_000,
+                })
+                .collect(),
+            rssi_interval: 50,
+            stream_rssi: true,
+            report_laps: false,
+        }
+    }
+}
+
+impl SimulatedPilot {
+    // Box-Muller transform, lap times are normally distributed around the mean.
+    fn next_lap_time(&self) -> u64 {
+        let mut rng = rand::thread_rng();
+        let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+        let u2: f64 = rng.gen();
+        let z = (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos();
+        let lap_time = self.mean_lap_time as f64 + z * self.lap_time_spread as f64;
+
+        lap_time.max(self.mean_lap_time as f64 / 2.0) as u64
+    }
+}
+
+struct SimulatedRace {
+    started_at: Instant,
+    previous_crossings: Vec<Option<u64>>,
+    next_crossings: Vec<u64>,
+}
+
+impl SimulatedRace {
+    fn new(pilots: &[SimulatedPilot]) -> SimulatedRace {
+        let mut rng = rand::thread_rng();
+
+        SimulatedRace {
+            started_at: Instant::now(),
+            previous_crossings: vec![None; pilots.len()],
+            next_crossings: pilots.iter().map(|_| rng.gen_range(1_000..3_000)).collect(),
+        }
+    }
+
+    fn rssi(&self, index: usize, timestamp: u64) -> u16 {
+        let distance = [self.previous_crossings[index], Some(self.next_crossings[index])]
+            .iter()
+            .flatten()
+            .map(|crossing| (timestamp as f64 - *crossing as f64).abs())
+            .fold(f64::MAX, f64::min);
+        let noise = rand::thread_rng().gen_range(-RSSI_NOISE..RSSI_NOISE);
+
+        (BASE_RSSI + PEAK_RSSI * (-(distance / PEAK_WIDTH).powi(2)).exp() + noise).max(0.0) as u16
+    }
+}
+
+struct SimulatorState {
+    powered_on_at: Instant,
+    race: Option<SimulatedRace>,
+}
+
+pub struct Simulator {
+    settings: SimulatorSettings,
+}
+
+impl Simulator {
+    pub fn new(settings: SimulatorSettings) -> Simulator {
+        Simulator { settings }
+    }
+}
+
+impl Transport for Simulator {
+    fn open(&mut self) -> io::Result<(Reader, Writer)> {
+        let (lines_tx, lines_rx) = channel();
+        let state = Arc::new(Mutex::new(SimulatorState {
+            powered_on_at: Instant::now(),
+            race: None,
+        }));
+
+        let settings = self.settings.clone();
+        let generator_state = state.clone();
+        let generator_tx = lines_tx.clone();
+        thread::spawn(move || generate(settings, generator_state, generator_tx));
+
+        let reader = ChannelReader::new(lines_rx);
+        let writer = CommandWriter {
+            pilots: self.settings.pilots.clone(),
+            state,
+            lines_tx,
+            buffer: Vec::new(),
+        };
+
+        Ok((Box::new(BufReader::new(reader)), Box::new(writer)))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            nodes: Some(self.settings.pilots.len() as u8),
+            lap_detection: self.settings.report_laps,
+            rssi_streaming: self.settings.stream_rssi,
+            frequency_control: true,
+        }
+    }
+}
+
+fn generate(settings: SimulatorSettings, state: Arc<Mutex<SimulatorState>>, lines_tx: Sender<Vec<u8>>) {
+    let mut last_heartbeat = Instant::now();
+
+    loop {
+        thread::sleep(Duration::from_millis(settings.rssi_interval));
+
+        let mut lines = Vec::new();
+        let mut state = state.lock().unwrap();
+
+        if last_heartbeat.elapsed() >= Duration::from_secs(1) {
+            last_heartbeat = Instant::now();
+            lines.push(format!("h:{}", state.powered_on_at.elapsed().as_millis()));
+        }
+
+        if let Some(race) = state.race.as_mut() {
+            let timestamp = race.started_at.elapsed().as_millis() as u64;
+
+            for (index, pilot) in settings.pilots.iter().enumerate() {
+                let node = index + 1;
+
+                if settings.stream_rssi {
+                    lines.push(format!("s:{}:{}:{}", node, timestamp, race.rssi(index, timestamp)));
+                }
+
+                let next_crossing = race.next_crossings[index];
+                if timestamp >= next_crossing {
+                    if settings.report_laps {
+                        lines.push(format!("l:{}:{}", node, next_crossing));
+                    }
+                    race.previous_crossings[index] = Some(next_crossing);
+                    race.next_crossings[index] = next_crossing + pilot.next_lap_time();
+                }
+            }
+        }
+
+        drop(state);
+
+        for line in lines {
+            if lines_tx.send(format!("{}\n", line).into_bytes()).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+struct CommandWriter {
+    pilots: Vec<SimulatedPilot>,
+    state: Arc<Mutex<SimulatorState>>,
+    lines_tx: Sender<Vec<u8>>,
+    buffer: Vec<u8>,
+}
+
+impl CommandWriter {
+    fn handle_command(&self, command: &str) -> String {
+        let mut state = self.state.lock().unwrap();
+        let node_command = command.split(':').next().unwrap_or_default();
+
+        match command {
+            "r:s" => {
+                state.race = Some(SimulatedRace::new(&self.pilots));
+                format!("a:{}", command)
+            }
+            "r:e" | "r:a" => {
+                state.race = None;
+                format!("a:{}", command)
+            }
+            "v" => "v:simulator-1.0".to_string(),
+            "p" => format!("a:{}", command),
+            _ if ["f", "t", "c"].contains(&node_command) => format!("a:{}", command),
+            _ => format!("e:Unknown command '{}'", command),
+        }
+    }
+}
+
+impl Write for CommandWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+
+        while let Some(position) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=position).collect();
+            let command = String::from_utf8_lossy(&line).trim().to_string();
+
+            if command.is_empty() {
+                continue;
+            }
+
+            let reply = self.handle_command(&command);
+            self.lines_tx
+                .send(format!("{}\n", reply).into_bytes())
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+
+    fn settings() -> SimulatorSettings {
+        SimulatorSettings {
+            pilots: vec![SimulatedPilot { mean_lap_time: 1_000, lap_time_spread: 100 }],
+            rssi_interval: 10,
+            stream_rssi: false,
+            report_laps: true,
+        }
+    }
+
+    // The reader times out like a serial port while the simulator has nothing to say.
+    fn read_line(reader: &mut Reader) -> String {
+        let mut line = String::new();
+
+        loop {
+            match reader.read_line(&mut line) {
+                Ok(_) => return line.trim().to_string(),
+                Err(error) if error.kind() == io::ErrorKind::TimedOut => continue,
+                Err(error) => panic!("{}", error),
+            }
+        }
+    }
+
+    fn read_reply(reader: &mut Reader) -> String {
+        loop {
+            let line = read_line(reader);
+
+            if !line.starts_with("h:") && !line.starts_with("l:") {
+                return line;
+            }
+        }
+    }
+
+    #[test]
+    fn replies_to_commands() {
+        let (mut reader, mut writer) = Simulator::new(settings()).open().unwrap();
+
+        writer.write_all(b"v\n").unwrap();
+        assert_eq!(read_reply(&mut reader), "v:simulator-1.0");
+        writer.write_all(b"r:s\n").unwrap();
+        assert_eq!(read_reply(&mut reader), "a:r:s");
+        writer.write_all(b"f:1:5658\n").unwrap();
+        assert_eq!(read_reply(&mut reader), "a:f:1:5658");
+        writer.write_all(b"r:e\n").unwrap();
+        assert_eq!(read_reply(&mut reader), "a:r:e");
+        writer.write_all(b"x:1\n").unwrap();
+        assert_eq!(read_reply(&mut reader), "e:Unknown command 'x:1'");
+    }
+
+    #[test]
+    fn reports_crossings_once_the_race_started() {
+        let (mut reader, mut writer) = Simulator::new(settings()).open().unwrap();
+
+        writer.write_all(b"r:s\n").unwrap();
+        assert_eq!(read_reply(&mut reader), "a:r:s");
+
+        let mut crossings = Vec::new();
+        while crossings.len() < 2 {
+            let line = read_line(&mut reader);
+
+            if line.starts_with("l:") {
+                crossings.push(line);
+            }
+        }
+
+        assert!(crossings.iter().all(|crossing| crossing.starts_with("l:1:")));
+    }
+
+    #[test]
+    fn capabilities_follow_the_settings() {
+        let capabilities = Simulator::new(settings()).capabilities();
+
+        assert_eq!(capabilities.nodes, Some(1));
+        assert!(capabilities.lap_detection);
+        assert!(!capabilities.rssi_streaming);
+    }
+}
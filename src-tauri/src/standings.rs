@@ -0,0 +1,398 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use crate::core::{Race, RaceStatus};
+use crate::leaderboard;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum TieBreak {
+    BestLap,
+    BestConsecutive { laps: u32 },
+    HeadToHead,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PointsSettings {
+    pub points: Vec<u32>,
+    pub tie_breaks: Vec<TieBreak>,
+}
+
+impl Default for PointsSettings {
+    fn default() -> Self {
+        PointsSettings {
+            points: vec![10, 7, 5, 4, 3, 2, 1],
+            tie_breaks: vec![TieBreak::BestLap, TieBreak::BestConsecutive { laps: 3 }, TieBreak::HeadToHead],
+        }
+    }
+}
+
+impl FromSql for PointsSettings {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().and_then(|s| {
+            serde_json::from_str(s).map_err(|e| FromSqlError::Other(Box::new(e)))
+        })
+    }
+}
+
+impl ToSql for PointsSettings {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        serde_json::to_string(self)
+            .map(ToSqlOutput::from)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+    }
+}
+
+impl PointsSettings {
+    pub fn points_for(&self, position: u32) -> u32 {
+        self.points.get(position as usize - 1).copied().unwrap_or(0)
+    }
+
+    fn consecutive_laps(&self) -> Vec<u32> {
+        let mut consecutive_laps: Vec<u32> = self.tie_breaks
+            .iter()
+            .filter_map(|tie_break| match tie_break {
+                TieBreak::BestConsecutive { laps } => Some(*laps),
+                _ => None,
+            })
+            .collect();
+
+        if consecutive_laps.is_empty() {
+            consecutive_laps.push(3);
+        }
+
+        consecutive_laps
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Standing {
+    pub position: u32,
+    pub pilot_id: i64,
+    pub points: u32,
+    pub races: u32,
+    pub wins: u32,
+    pub best_lap: Option<i64>,
+    pub best_consecutive: Option<i64>,
+}
+
+// Finishing positions of every pilot, one map per finished race.
+type RaceResults = Vec<HashMap<i64, u32>>;
+
+fn min_option(a: Option<i64>, b: Option<i64>) -> Option<i64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+// Missing times are always worse than any recorded time.
+fn compare_times(a: Option<i64>, b: Option<i64>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+fn compare_head_to_head(a: i64, b: i64, results: &RaceResults) -> Ordering {
+    let (a_ahead, b_ahead) = results
+        .iter()
+        .filter_map(|race| Some((*race.get(&a)?, *race.get(&b)?)))
+        .fold((0, 0), |(a_ahead, b_ahead), (a_position, b_position)| {
+            if a_position < b_position {
+                (a_ahead + 1, b_ahead)
+            } else {
+                (a_ahead, b_ahead + 1)
+            }
+        });
+
+    b_ahead.cmp(&a_ahead)
+}
+
+pub fn compute(races: &[Race], settings: &PointsSettings) -> Vec<Standing> {
+    let mut standings: Vec<Standing> = Vec::new();
+    let mut best_consecutive: HashMap<(i64, u32), Option<i64>> = HashMap::new();
+    let consecutive_laps = settings.consecutive_laps();
+    let mut results: RaceResults = Vec::new();
+
+    for race in races.iter().filter(|race| race.status == RaceStatus::Finished) {
+        let mut race_results = HashMap::new();
+
+        for entry in leaderboard::compute(&race.heats) {
+            race_results.insert(entry.pilot_id, entry.position);
+
+            let heat = race.heats.iter().find(|heat| heat.id == entry.heat_id).unwrap();
+            for laps in &consecutive_laps {
+                let best = best_consecutive.entry((entry.pilot_id, *laps)).or_insert(None);
+                *best = min_option(*best, leaderboard::best_consecutive(&heat.laps, *laps));
+            }
+
+            let index = match standings.iter().position(|standing| standing.pilot_id == entry.pilot_id) {
+                Some(index) => index,
+                None => {
+                    standings.push(Standing {
+                        position: 0,
+                        pilot_id: entry.pilot_id,
+                        points: 0,
+                        races: 0,
+                        wins: 0,
+                        best_lap: None,
+                        best_consecutive: None,
+                    });
+                    standings.len() - 1
+                }
+            };
+
+            let standing = &mut standings[index];
+            if entry.laps > 0 {
+                standing.points += settings.points_for(entry.position);
+            }
+            standing.races += 1;
+            standing.wins += (entry.position == 1) as u32;
+            standing.best_lap = min_option(standing.best_lap, entry.best_lap);
+            standing.best_consecutive = best_consecutive[&(entry.pilot_id, consecutive_laps[0])];
+        }
+
+        results.push(race_results);
+    }
+
+    standings.sort_by(|a, b| {
+        settings.tie_breaks.iter().fold(b.points.cmp(&a.points), |ordering, tie_break| {
+            ordering.then_with(|| match tie_break {
+                TieBreak::BestLap => compare_times(a.best_lap, b.best_lap),
+                TieBreak::BestConsecutive { laps } => compare_times(
+                    best_consecutive[&(a.pilot_id, *laps)],
+                    best_consecutive[&(b.pilot_id, *laps)],
+                ),
+                TieBreak::HeadToHead => compare_head_to_head(a.pilot_id, b.pilot_id, &results),
+            })
+        }).then(a.pilot_id.cmp(&b.pilot_id))
+    });
+
+    for (index, standing) in standings.iter_mut().enumerate() {
+        standing.position = index as u32 + 1;
+    }
+
+    standings
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ConsecutiveRanking {
+    pub position: u32,
+    pub pilot_id: i64,
+    pub time: Option<i64>,
+    pub race_id: Option<i64>,
+    pub laps: u32,
+}
+
+// Qualifying ranking by the fastest window of `count` consecutive laps flown in a single race. Pilots who
+// never completed that many laps in one race are ranked after everyone else by the laps they have flown.
+// Windows start at the first full lap, the holeshot is not part of any of them.
+pub fn compute_consecutive(races: &[Race], count: u32) -> Vec<ConsecutiveRanking> {
+    let mut rankings: Vec<ConsecutiveRanking> = Vec::new();
+
+    for race in races.iter().filter(|race| race.status == RaceStatus::Finished) {
+        for heat in &race.heats {
+            let time = leaderboard::best_consecutive(&heat.laps, count);
+            let laps = heat.laps.iter().filter(|lap| lap.is_full_lap()).count() as u32;
+
+            match rankings.iter_mut().find(|ranking| ranking.pilot_id == heat.pilot_id) {
+                Some(ranking) => {
+                    if compare_times(time, ranking.time) == Ordering::Less {
+                        ranking.time = time;
+                        ranking.race_id = Some(race.id);
+                    }
+                    ranking.laps += laps;
+                }
+                None => rankings.push(ConsecutiveRanking {
+                    position: 0,
+                    pilot_id: heat.pilot_id,
+                    time,
+                    race_id: time.map(|_| race.id),
+                    laps,
+                }),
+            }
+        }
+    }
+
+    rankings.sort_by(|a, b| {
+        compare_times(a.time, b.time)
+            .then(b.laps.cmp(&a.laps))
+            .then(a.pilot_id.cmp(&b.pilot_id))
+    });
+
+    for (index, ranking) in rankings.iter_mut().enumerate() {
+        ranking.position = index as u32 + 1;
+    }
+
+    rankings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Heat, Lap, LapSource};
+
+    // The holeshot is crossed a second after the start, `lap_times` are the full laps after it.
+    fn heat(id: i64, node: u8, pilot_id: i64, lap_times: &[i64]) -> Heat {
+        let mut crossed_at = 1000;
+        let mut laps = vec![Lap::new(id * 100, id, 0, crossed_at, crossed_at, LapSource::Device, false)];
+
+        for (index, lap_time) in lap_times.iter().enumerate() {
+            crossed_at += lap_time;
+            laps.push(Lap::new(id * 100 + index as i64 + 1, id, index as u32 + 1, crossed_at, *lap_time, LapSource::Device, false));
+        }
+
+        Heat::new(id, node, format!("R{}", node).parse().unwrap(), pilot_id, laps)
+    }
+
+    fn race(id: i64, status: RaceStatus, heats: Vec<Heat>) -> Race {
+        Race::new(id, format!("Race {}", id), status, heats, Default::default(), None, None, None)
+    }
+
+    fn settings(points: Vec<u32>) -> PointsSettings {
+        PointsSettings { points, ..Default::default() }
+    }
+
+    fn pilot_ids(standings: &[Standing]) -> Vec<i64> {
+        standings.iter().map(|standing| standing.pilot_id).collect()
+    }
+
+    #[test]
+    fn sums_points_of_finished_races() {
+        let races = vec![
+            race(1, RaceStatus::Finished, vec![heat(11, 1, 1, &[20_000, 20_000]), heat(12, 2, 2, &[21_000])]),
+            race(2, RaceStatus::Finished, vec![heat(21, 1, 1, &[22_000]), heat(22, 2, 2, &[19_000, 19_000])]),
+            race(3, RaceStatus::InProgress, vec![heat(31, 1, 1, &[]), heat(32, 2, 2, &[18_000, 18_000])]),
+        ];
+        let standings = compute(&races, &settings(vec![10, 7]));
+
+        assert_eq!(pilot_ids(&standings), vec![2, 1]);
+        assert_eq!(standings.iter().map(|standing| standing.points).collect::<Vec<u32>>(), vec![17, 17]);
+        assert_eq!(standings[0].races, 2);
+        assert_eq!(standings[0].wins, 1);
+        assert_eq!(standings[0].best_lap, Some(19_000));
+    }
+
+    #[test]
+    fn gives_points_only_to_pilots_with_laps() {
+        let races = vec![race(1, RaceStatus::Finished, vec![heat(11, 1, 1, &[20_000]), heat(12, 2, 2, &[])])];
+        let standings = compute(&races, &settings(vec![10, 7]));
+
+        assert_eq!(standings[1].pilot_id, 2);
+        assert_eq!(standings[1].points, 0);
+        assert_eq!(standings[1].races, 1);
+    }
+
+    #[test]
+    fn ignores_the_holeshot_for_best_times() {
+        let races = vec![race(1, RaceStatus::Finished, vec![heat(11, 1, 1, &[20_000, 21_000, 22_000])])];
+        let standings = compute(&races, &PointsSettings::default());
+
+        assert_eq!(standings[0].best_lap, Some(20_000));
+        assert_eq!(standings[0].best_consecutive, Some(63_000));
+    }
+
+    #[test]
+    fn breaks_ties_by_best_lap_first() {
+        let races = vec![race(1, RaceStatus::Finished, vec![
+            heat(11, 1, 1, &[20_000, 20_000]),
+            heat(12, 2, 2, &[19_000, 30_000]),
+        ])];
+        let standings = compute(&races, &settings(vec![1, 1]));
+
+        assert_eq!(pilot_ids(&standings), vec![2, 1]);
+    }
+
+    #[test]
+    fn breaks_ties_by_best_consecutive_after_best_lap() {
+        let races = vec![race(1, RaceStatus::Finished, vec![
+            heat(11, 1, 1, &[18_000, 22_000, 22_000, 22_000]),
+            heat(12, 2, 2, &[18_000, 19_000, 19_000]),
+        ])];
+        let standings = compute(&races, &settings(vec![1, 1]));
+
+        assert_eq!(pilot_ids(&standings), vec![2, 1]);
+    }
+
+    #[test]
+    fn breaks_ties_head_to_head_last() {
+        let races = vec![race(1, RaceStatus::Finished, vec![
+            heat(11, 2, 1, &[18_000, 20_000]),
+            heat(12, 1, 2, &[18_000, 20_000]),
+        ])];
+        let standings = compute(&races, &settings(vec![1, 1]));
+
+        assert_eq!(pilot_ids(&standings), vec![2, 1]);
+    }
+
+    #[test]
+    fn follows_the_configured_tie_breaks() {
+        let races = vec![race(1, RaceStatus::Finished, vec![
+            heat(11, 1, 1, &[20_000, 20_000]),
+            heat(12, 2, 2, &[19_000, 30_000]),
+        ])];
+        let settings = PointsSettings { points: vec![1, 1], tie_breaks: vec![TieBreak::HeadToHead] };
+
+        assert_eq!(pilot_ids(&compute(&races, &settings)), vec![1, 2]);
+    }
+
+    fn rankings(races: &[Race], count: u32) -> Vec<(i64, Option<i64>, Option<i64>, u32)> {
+        compute_consecutive(races, count)
+            .into_iter()
+            .map(|ranking| (ranking.pilot_id, ranking.time, ranking.race_id, ranking.laps))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_consecutive_laps_within_one_race() {
+        let races = vec![
+            race(1, RaceStatus::Finished, vec![heat(11, 1, 1, &[20_000, 20_000])]),
+            race(2, RaceStatus::Finished, vec![heat(21, 1, 1, &[20_000, 20_000])]),
+        ];
+
+        assert_eq!(rankings(&races, 3), vec![(1, None, None, 4)]);
+        assert_eq!(rankings(&races, 2), vec![(1, Some(40_000), Some(1), 4)]);
+    }
+
+    #[test]
+    fn keeps_the_race_of_the_best_window() {
+        let races = vec![
+            race(1, RaceStatus::Finished, vec![heat(11, 1, 1, &[20_000, 20_000])]),
+            race(2, RaceStatus::Finished, vec![heat(21, 1, 1, &[25_000, 19_000, 19_000])]),
+        ];
+
+        assert_eq!(rankings(&races, 2), vec![(1, Some(38_000), Some(2), 5)]);
+    }
+
+    #[test]
+    fn leaves_the_holeshot_out_of_the_windows() {
+        let races = vec![race(1, RaceStatus::Finished, vec![heat(11, 1, 1, &[20_000, 21_000])])];
+
+        assert_eq!(rankings(&races, 3), vec![(1, None, None, 2)]);
+        assert_eq!(rankings(&races, 2), vec![(1, Some(41_000), Some(1), 2)]);
+    }
+
+    #[test]
+    fn ranks_pilots_without_a_full_window_by_laps() {
+        let races = vec![race(1, RaceStatus::Finished, vec![
+            heat(11, 1, 1, &[20_000]),
+            heat(12, 2, 2, &[20_000, 20_000]),
+            heat(13, 3, 3, &[25_000, 25_000, 25_000]),
+            heat(14, 4, 4, &[20_000, 20_000]),
+            heat(15, 5, 5, &[19_000, 19_000, 19_000]),
+        ])];
+        let pilot_ids: Vec<i64> = rankings(&races, 3).iter().map(|ranking| ranking.0).collect();
+
+        assert_eq!(pilot_ids, vec![5, 3, 2, 4, 1]);
+    }
+
+    #[test]
+    fn ranks_only_finished_races() {
+        let races = vec![race(1, RaceStatus::Interrupted, vec![heat(11, 1, 1, &[20_000, 20_000, 20_000])])];
+
+        assert!(compute_consecutive(&races, 3).is_empty());
+    }
+}
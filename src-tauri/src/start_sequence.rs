@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+use rand::Rng;
+use tauri::AppHandle;
+use tokio::select;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+use crate::events;
+use crate::events::AppEvent;
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct StartSequenceSettings {
+    pub staging_seconds: u32,
+    pub min_delay: u64,
+    pub max_delay: u64,
+    pub false_start_window: u64,
+}
+
+impl Default for StartSequenceSettings {
+    fn default() -> Self {
+        StartSequenceSettings {
+            staging_seconds: 3,
+            min_delay: 1000,
+            max_delay: 5000,
+            false_start_window: 100,
+        }
+    }
+}
+
+impl StartSequenceSettings {
+    pub fn random_delay(&self) -> Duration {
+        Duration::from_millis(rand::thread_rng().gen_range(self.min_delay..=self.max_delay))
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub enum StartPhase {
+    Staging { remaining: u32 },
+    Ready,
+    Go,
+    FalseStart { node: u8 },
+    Cancelled,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct StartSequenceEvent {
+    race_id: i64,
+    phase: StartPhase,
+}
+
+pub fn emit_phase(app_handle: &AppHandle, race_id: i64, phase: StartPhase) {
+    events::emit(app_handle, AppEvent::StartSequence(StartSequenceEvent { race_id, phase }));
+}
+
+pub async fn run(
+    settings: StartSequenceSettings,
+    race_id: i64,
+    app_handle: AppHandle,
+    cancel: CancellationToken,
+) -> Option<Instant> {
+    for remaining in (1..=settings.staging_seconds).rev() {
+        emit_phase(&app_handle, race_id, StartPhase::Staging { remaining });
+
+        select! {
+            _ = cancel.cancelled() => return None,
+            _ = sleep(Duration::from_secs(1)) => {}
+        }
+    }
+
+    emit_phase(&app_handle, race_id, StartPhase::Ready);
+
+    select! {
+        _ = cancel.cancelled() => return None,
+        _ = sleep(settings.random_delay()) => {}
+    }
+
+    let go_at = Instant::now();
+    emit_phase(&app_handle, race_id, StartPhase::Go);
+
+    Some(go_at)
+}
+
+// Maps the timer's own clock, which restarts with every `StartRace` command, onto the race clock
+// whose zero is the start tone.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RaceClock {
+    device_offset: i64,
+    race_time: i64,
+    running_since: Option<Instant>,
+}
+
+impl RaceClock {
+    pub fn start(armed_at: Instant, go_at: Instant) -> RaceClock {
+        RaceClock {
+            device_offset: go_at.duration_since(armed_at).as_millis() as i64,
+            race_time: 0,
+            running_since: Some(go_at),
+        }
+    }
+
+    pub fn pause(&mut self, now: Instant) {
+        self.race_time = self.elapsed(now);
+        self.running_since = None;
+    }
+
+    pub fn resume(&mut self, armed_at: Instant) {
+        self.device_offset = -self.race_time;
+        self.running_since = Some(armed_at);
+    }
+
+    pub fn elapsed(&self, now: Instant) -> i64 {
+        match self.running_since {
+            Some(running_since) => self.race_time + now.duration_since(running_since).as_millis() as i64,
+            None => self.race_time,
+        }
+    }
+
+    pub fn race_time(&self, device_timestamp: u64) -> i64 {
+        device_timestamp as i64 - self.device_offset
+    }
+}
@@ -0,0 +1,211 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use rusqlite::Connection;
+use crate::db::DbError;
+
+const DATA_DIR_FLAG: &str = "--data-dir";
+const DATA_DIR_ENV: &str = "PATLIMER_DATA_DIR";
+const MAIN_DB_FILE: &str = "db.sqlite";
+const EVENTS_DIR: &str = "events";
+const LEGACY_MAIN_DB_FILE: &str = "db";
+// SQLite keeps uncommitted or not yet checkpointed changes next to the database, they have to move with it.
+const SIDECAR_SUFFIXES: [&str; 3] = ["-journal", "-wal", "-shm"];
+
+static DATA_ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+// `--data-dir <path>` wins over PATLIMER_DATA_DIR, which wins over the OS app data directory.
+pub fn resolve_data_root(args: &[String], app_data_dir: Option<PathBuf>) -> PathBuf {
+    select_data_root(args, env::var(DATA_DIR_ENV).ok(), app_data_dir)
+}
+
+fn select_data_root(args: &[String], env_dir: Option<String>, app_data_dir: Option<PathBuf>) -> PathBuf {
+    let from_args = args.iter().enumerate().find_map(|(index, arg)| {
+        if arg == DATA_DIR_FLAG {
+            args.get(index + 1).cloned()
+        } else {
+            arg.strip_prefix(DATA_DIR_FLAG).and_then(|rest| rest.strip_prefix('=')).map(String::from)
+        }
+    });
+
+    from_args
+        .or(env_dir)
+        .map(PathBuf::from)
+        .or(app_data_dir)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+pub fn init(root: PathBuf) -> Result<(), DbError> {
+    fs::create_dir_all(root.join(EVENTS_DIR))
+        .map_err(|e| DbError::Invalid(format!("Can not create the data directory '{}': {}", root.display(), e)))?;
+    println!("Storing data in {}", root.display());
+
+    DATA_ROOT
+        .set(root)
+        .map_err(|root| DbError::Invalid(format!("The data directory is already set, can not change it to '{}'", root.display())))
+}
+
+// Paths are only asked for after a successful `init`, the working directory mirrors the last fallback of
+// `resolve_data_root`.
+fn data_root() -> &'static Path {
+    DATA_ROOT.get().map_or(Path::new("."), |root| root.as_path())
+}
+
+pub fn main_db_path() -> PathBuf {
+    data_root().join(MAIN_DB_FILE)
+}
+
+pub fn event_db_path(race_event_id: i64) -> PathBuf {
+    data_root().join(EVENTS_DIR).join(format!("{}.sqlite", race_event_id))
+}
+
+// Earlier versions kept `db` and one file per race event, named by its id, in the working directory. They are
+// moved into the data directory once, before it has a database of its own.
+pub fn migrate_legacy_files(working_dir: &Path) {
+    let legacy_main_db = working_dir.join(LEGACY_MAIN_DB_FILE);
+
+    if main_db_path().exists() || !legacy_main_db.is_file() {
+        return;
+    }
+
+    let race_event_ids = match find_race_event_ids(&legacy_main_db) {
+        Ok(race_event_ids) => race_event_ids,
+        Err(e) => {
+            println!("Skipping '{}', it is not a race events database: {}", legacy_main_db.display(), e);
+            return;
+        }
+    };
+
+    move_database(&legacy_main_db, &main_db_path());
+
+    for race_event_id in race_event_ids {
+        let legacy_event_db = working_dir.join(race_event_id.to_string());
+
+        if legacy_event_db.is_file() && !event_db_path(race_event_id).exists() {
+            move_database(&legacy_event_db, &event_db_path(race_event_id));
+        }
+    }
+}
+
+fn find_race_event_ids(path: &Path) -> rusqlite::Result<Vec<i64>> {
+    let connection = Connection::open(path)?;
+    let mut statement = connection.prepare("SELECT id FROM raceEvents")?;
+    let race_event_ids = statement.query_map([], |row| row.get(0))?.collect();
+
+    race_event_ids
+}
+
+fn move_database(from: &Path, to: &Path) {
+    move_file(from, to);
+
+    for suffix in SIDECAR_SUFFIXES {
+        let sidecar = with_suffix(from, suffix);
+        if sidecar.is_file() {
+            move_file(&sidecar, &with_suffix(to, suffix));
+        }
+    }
+}
+
+pub fn remove_database(path: &Path) {
+    let sidecars = SIDECAR_SUFFIXES.iter().map(|suffix| with_suffix(path, suffix));
+
+    for file in std::iter::once(path.to_path_buf()).chain(sidecars) {
+        if file.is_file() {
+            if let Err(e) = fs::remove_file(&file) {
+                println!("Can not remove '{}': {}", file.display(), e);
+            }
+        }
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+
+    PathBuf::from(path)
+}
+
+fn move_file(from: &Path, to: &Path) {
+    // Renaming fails when the data directory is on another file system.
+    let result = fs::rename(from, to).or_else(|_| fs::copy(from, to).and_then(|_| fs::remove_file(from)));
+
+    match result {
+        Ok(_) => println!("Moved '{}' to '{}'", from.display(), to.display()),
+        Err(e) => println!("Can not move '{}' to '{}': {}", from.display(), to.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn app_data_dir() -> Option<PathBuf> {
+        Some(PathBuf::from("/app"))
+    }
+
+    #[test]
+    fn data_dir_flag_with_separate_value() {
+        let root = select_data_root(&args(&["patlimer", "--data-dir", "x"]), Some("env".to_string()), app_data_dir());
+        assert_eq!(root, PathBuf::from("x"));
+    }
+
+    #[test]
+    fn data_dir_flag_with_equals() {
+        let root = select_data_root(&args(&["patlimer", "--data-dir=x"]), Some("env".to_string()), app_data_dir());
+        assert_eq!(root, PathBuf::from("x"));
+    }
+
+    #[test]
+    fn env_var_wins_over_app_data_dir() {
+        let root = select_data_root(&args(&["patlimer"]), Some("env".to_string()), app_data_dir());
+        assert_eq!(root, PathBuf::from("env"));
+    }
+
+    #[test]
+    fn falls_back_to_app_data_dir_then_working_dir() {
+        assert_eq!(select_data_root(&args(&["patlimer"]), None, app_data_dir()), PathBuf::from("/app"));
+        assert_eq!(select_data_root(&args(&["patlimer", "--data-dir"]), None, None), PathBuf::from("."));
+    }
+
+    #[test]
+    fn moves_sidecars_with_the_database() {
+        let dir = env::temp_dir().join(format!("patlimer-storage-{}", std::process::id()));
+        fs::create_dir_all(dir.join("to")).unwrap();
+        for name in ["db", "db-journal", "db-wal"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+
+        move_database(&dir.join("db"), &dir.join("to").join("db.sqlite"));
+
+        assert_eq!(fs::read_to_string(dir.join("to").join("db.sqlite")).unwrap(), "db");
+        assert_eq!(fs::read_to_string(dir.join("to").join("db.sqlite-journal")).unwrap(), "db-journal");
+        assert_eq!(fs::read_to_string(dir.join("to").join("db.sqlite-wal")).unwrap(), "db-wal");
+        assert!(!dir.join("to").join("db.sqlite-shm").exists());
+        assert!(!dir.join("db-journal").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn removes_sidecars_with_the_database() {
+        let dir = env::temp_dir().join(format!("patlimer-storage-remove-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["1.sqlite", "1.sqlite-wal", "1.sqlite-shm", "2.sqlite"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+
+        remove_database(&dir.join("1.sqlite"));
+
+        for name in ["1.sqlite", "1.sqlite-wal", "1.sqlite-shm"] {
+            assert!(!dir.join(name).exists(), "{} was not removed", name);
+        }
+        assert!(dir.join("2.sqlite").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
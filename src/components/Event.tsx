@@ -3,10 +3,19 @@ import { useAppState } from "../store";
 import { Races } from "./Races";
 
 export function Event() {
-  const [state, {raceEvents, races}] = useAppState();
+  const [state, {raceEvents, races, audit}] = useAppState();
 
   const [openSettings, setOpenSettings] = createSignal(false);
 
+  const [openAuditLog, setOpenAuditLog] = createSignal(false);
+
+  const toggleAuditLog = () => {
+    if (!openAuditLog()) {
+      audit.load();
+    }
+    setOpenAuditLog(open => !open);
+  }
+
   const selectedRaceEvent = () => state.raceEvents.find(raceEvent => raceEvent.id === state.selectedRaceEventId);
 
   const isEmpty = () => !state.pilots.length || !state.races.length;
@@ -20,16 +29,43 @@ export function Event() {
     <Show when={openSettings()}>
       <button onClick={() => setOpenSettings(false)}>Back</button>
     </Show>
+    <label>
+      Operator
+      <input value={state.operator} onChange={e => audit.setOperator(e.currentTarget.value)}/>
+    </label>
+    <button onClick={() => toggleAuditLog()}>{openAuditLog() ? "Hide audit log" : "Audit log"}</button>
+    <Show when={openAuditLog()}>
+      <ul>
+        <For each={state.auditLog} fallback={<span>No recorded actions</span>}>
+          {(entry) => <li>
+            {new Date(entry.created_at / 1000).toLocaleString()} {entry.actor}: {entry.action} {entry.subject}
+          </li>}
+        </For>
+      </ul>
+    </Show>
     <Switch>
       <Match when={!openSettings()}>
         <Show when={!isEmpty()} fallback={<button onClick={() => setOpenSettings(true)}>Go to Settings</button>}>
           <ul>
             <For each={state.races}>
-              {(item) => <li>{item.name} {item.status}</li>}
+              {(item) => <li>
+                {item.name} {item.status}
+                <Switch>
+                  <Match when={item.status === "New"}>
+                    <button onClick={() => races.startRace(item.id)}>Start</button>
+                  </Match>
+                  <Match when={item.status === "InProgress"}>
+                    <button onClick={() => races.stopRace(item.id)}>Stop</button>
+                    <button onClick={() => races.interruptRace(item.id)}>Interrupt</button>
+                  </Match>
+                  <Match when={item.status === "Interrupted"}>
+                    <button onClick={() => races.resumeRace(item.id)}>Resume</button>
+                    <button onClick={() => races.finishRace(item.id)}>Finish</button>
+                  </Match>
+                </Switch>
+              </li>}
             </For>
           </ul>
-
-          <button onClick={() => races.startRace()}>Start Race</button>
         </Show>
       </Match>
       <Match when={openSettings()}>
@@ -1,18 +1,21 @@
 import "./Races.scss";
-import {Heat, NewHeatDto, NewRaceDto, Pilot, Race, Slot} from "../models";
+import {Heat, InterferenceIssue, NewHeatDto, NewRaceDto, Pilot, Race, Slot, VideoSystem} from "../models";
 import {createSignal, For} from "solid-js";
 import {invoke} from "@tauri-apps/api/tauri";
 import {useAppState} from "../store";
 
 const channels = ["R1", "R3", "R6", "R7"];
+const videoSystems: VideoSystem[] = ["Analog", "DjiO3", "HdZero"];
 
 export function Races() {
-    const [state, {pilots, races}] = useAppState();
+    const [state, {pilots, races, channels: lineup}] = useAppState();
 
     const [newPilotName, setNewPilotName] = createSignal("");
 
     const [slots, setSlots] = createSignal<Slot[]>([]);
 
+    const [issues, setIssues] = createSignal<InterferenceIssue[]>([]);
+
     const addPilot = (event: Event) => {
         event.preventDefault();
         pilots.addOne(newPilotName());
@@ -35,6 +38,9 @@ export function Races() {
 
     const updateChannel = (index: number, channel: string) => {
         updateSlot(index, {channel});
+        lineup.check(slots().map(slot => slot.channel).filter(channel => channel))
+            .then(setIssues)
+            .catch(console.log);
     }
 
     const updatePilot = (index: number, pilot_id: number) => {
@@ -44,25 +50,42 @@ export function Races() {
     const addRace = () => {
         const heats: NewHeatDto[] = slots().map(({channel, pilot_id}, index) => ({
             no: index + 1,
-            channel,
+            channel: channel || undefined,
             pilot_id,
         }));
         races.addOne(heats);
     }
 
-    const isRaceFormValid = () => slots().length && slots().every(slot => slot.channel && slot.pilot_id);
+    const generateRaces = () => {
+        races.generate({nodes: channels.length, seeding: {type: "RoundRobin"}});
+    }
+
+    const isRaceFormValid = () => slots().length && slots().every(slot => slot.pilot_id);
 
 
     return (<div class="races-root">
         <div class="pilots">
             <h2>Pilots</h2>
             <form onSubmit={(e) => addPilot(e)}>
-                <input onChange={e => setNewPilotName(e.currentTarget.value)} value={newPilotName()}/>
+                <input list="pilot-profiles" onChange={e => setNewPilotName(e.currentTarget.value)} value={newPilotName()}/>
+                <datalist id="pilot-profiles">
+                    <For each={state.pilotProfiles}>
+                        {profile => <option value={profile.callsign}>{profile.real_name}</option>}
+                    </For>
+                </datalist>
                 <button type="submit">Add Pilot</button>
             </form>
+            <button disabled={!state.pilots.some(pilot => pilot.profile_id === null)} onClick={() => pilots.importPilots()}>Import pilots to registry</button>
             <ul>
                 <For each={state.pilots} fallback={<span>No added pilots</span>}>
-                    {(item) => <li>{item.name}</li>}
+                    {(item) => <li>
+                        {item.name}
+                        <select value={item.video_system} onChange={e => pilots.setVideoSystem(item.id, e.currentTarget.value as VideoSystem)}>
+                            <For each={videoSystems}>
+                                {videoSystem => <option value={videoSystem}>{videoSystem}</option>}
+                            </For>
+                        </select>
+                    </li>}
                 </For>
             </ul>
         </div>
@@ -72,9 +95,12 @@ export function Races() {
                 <For each={state.races} fallback={<span>No added races</span>}>
                     {(item) => <li>{item.name} - <For each={item.heats}>
                         {heat => <span>{heat.channel}:{state.pilots.find(pilot => pilot.id === heat.pilot_id)?.name}</span>}
-                    </For></li>}
+                    </For>
+                        <button disabled={item.status !== "New"} onClick={() => races.assignChannels(item.id)}>Assign channels</button>
+                    </li>}
                 </For>
             </ul>
+            <button disabled={!state.pilots.length} onClick={() => generateRaces()}>Generate races</button>
             <h3>New race</h3>
             <button disabled={slots().length >= 4} onClick={() => addSlot()}>Add slot</button>
             <div class="races__slots">
@@ -82,7 +108,7 @@ export function Races() {
                     {(item, index) => <label>
                         Slot {index() + 1}
                         <select value={item?.channel} onChange={e => updateChannel(index(), e.currentTarget.value)}>
-                            <option value="">-- Select channel --</option>
+                            <option value="">-- Auto --</option>
                             <For each={channels}>
                                 {channel => <option value={channel}>{channel}</option>}
                             </For>
@@ -97,7 +123,12 @@ export function Races() {
                     </label>}
                 </For>
             </div>
-            <button onClick={() => addRace()} disabled={!isRaceFormValid()}>Add race</button>
+            <ul>
+                <For each={issues()}>
+                    {issue => <li>{issue.severity}: {issue.message}</li>}
+                </For>
+            </ul>
+            <button onClick={() => addRace()} disabled={!isRaceFormValid() || issues().some(issue => issue.severity === "Error")}>Add race</button>
         </div>
     </div>)
 }
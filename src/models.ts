@@ -1,35 +1,92 @@
+export type VideoSystem = "Analog" | "DjiO3" | "HdZero";
+
 export interface Pilot {
     id: number;
     name: string;
     raceEventId: number;
+    video_system: VideoSystem;
+    profile_id: number | null;
+    preferred_channel: string | null;
+}
+
+export interface PilotProfile {
+    id: number;
+    callsign: string;
+    real_name: string | null;
+    country: string | null;
+    team: string | null;
+    video_system: VideoSystem;
+    preferred_channel: string | null;
+    transponder: string | null;
+    phonetic_name: string | null;
+    avatar_path: string | null;
+}
+
+export type NewPilotProfileDto = Partial<Omit<PilotProfile, "id">> & { callsign: string };
+
+export interface PilotVideoSystemDto {
+    race_event_id: number;
+    pilot_id: number;
+    video_system: VideoSystem;
+}
+
+export type LapSource = "Device" | "Manual";
+
+export interface Lap {
+    id: number;
+    heat_id: number;
+    no: number;
+    crossed_at: number;
+    lap_time: number;
+    source: LapSource;
+    deleted: boolean;
 }
 
 export interface Heat {
+    id: number;
     no: number;
     channel: string;
     pilot_id: number;
+    laps: Lap[];
 }
 
 export type RaceStatus = "New" | "InProgress" | "Interrupted" | "Finished";
 
+export type RaceFormat =
+    | { type: "Manual" }
+    | { type: "FirstToLaps"; laps: number }
+    | { type: "Timed"; duration: number; grace: number }
+    | { type: "FixedTime"; duration: number; max_laps: number };
+
 export interface Race {
     id: string;
     name: string;
     status: RaceStatus;
     heats: Heat[];
+    format: RaceFormat;
+    started_at: number | null;
+    finished_at: number | null;
+    round_id: number | null;
     raceEventId: number;
 }
 
+export interface RaceRefDto {
+    race_event_id: number;
+    race_id: string;
+}
+
 export interface NewHeatDto {
     no: number;
     pilot_id: number;
-    channel: string;
+    channel?: string;
 }
 
 export interface NewRaceDto {
     name: string;
     heats: NewHeatDto[];
     race_event_id: number;
+    format?: RaceFormat;
+    round_id?: number;
 }
 
 export interface Slot {
@@ -51,4 +108,285 @@ export interface NewRaceEventDto {
 export interface RaceDetailsDto {
     pilots: Pilot[];
     races: Race[];
+    classes: Class[];
+}
+
+export interface PortInfo {
+    port_name: string;
+    vid: number | null;
+    pid: number | null;
+    serial_number: string | null;
+    manufacturer: string | null;
+    product: string | null;
+}
+
+export type DeviceSelection =
+    | { type: "Serial"; port_name: string; baud_rate: number }
+    | { type: "Tcp"; address: string }
+    | { type: "Simulator"; settings?: Partial<SimulatorSettings> };
+
+export interface SimulatedPilot {
+    mean_lap_time: number;
+    lap_time_spread: number;
+}
+
+export interface SimulatorSettings {
+    pilots: SimulatedPilot[];
+    rssi_interval: number;
+    stream_rssi: boolean;
+    report_laps: boolean;
+}
+
+export interface Capabilities {
+    nodes: number | null;
+    lap_detection: boolean;
+    rssi_streaming: boolean;
+    frequency_control: boolean;
+}
+
+export interface ConnectionStatus {
+    state: "Disconnected" | "Connected" | "Reconnecting";
+    selection: DeviceSelection | null;
+    capabilities: Capabilities | null;
+    error: string | null;
+}
+
+export interface LapRecordedEvent {
+    race_event_id: number;
+    race_id: string;
+    lap: Lap;
+    laps: Lap[];
+}
+
+export interface LapsCorrectedEvent {
+    race_event_id: number;
+    race_id: string;
+    heat_id: number;
+    laps: Lap[];
+}
+
+export interface NewLapDto {
+    race_event_id: number;
+    race_id: string;
+    heat_id: number;
+    crossed_at?: number;
+}
+
+export interface AuditEntry {
+    id: number;
+    created_at: number;
+    actor: string;
+    action: string;
+    race_id: number | null;
+    subject: string | null;
+    before: unknown;
+    after: unknown;
+}
+
+export interface AuditLogFilterDto {
+    race_event_id: number;
+    race_id?: number;
+}
+
+export interface LapRefDto {
+    race_event_id: number;
+    race_id: string;
+    lap_id: number;
+}
+
+export interface SplitLapDto extends LapRefDto {
+    crossed_at?: number;
+}
+
+export interface RaceStatusChangedEvent {
+    race_event_id: number;
+    race: Race;
+}
+
+export interface RssiSample {
+    node: number;
+    timestamp: number;
+    rssi: number;
+}
+
+export interface RssiBatchEvent {
+    samples: RssiSample[];
+}
+
+export type Gap = { Time: number } | { Laps: number };
+
+export interface LeaderboardEntry {
+    position: number;
+    heat_id: number;
+    pilot_id: number;
+    node: number;
+    laps: number;
+    total_time: number;
+    last_lap: number | null;
+    best_lap: number | null;
+    gap_to_leader: Gap | null;
+    gap_to_next: Gap | null;
+}
+
+export interface LeaderboardUpdatedEvent {
+    race_event_id: number;
+    race_id: string;
+    entries: LeaderboardEntry[];
+}
+
+export type TieBreak =
+    | { type: "BestLap" }
+    | { type: "BestConsecutive"; laps: number }
+    | { type: "HeadToHead" };
+
+export interface PointsSettings {
+    points: number[];
+    tie_breaks: TieBreak[];
+}
+
+export interface Standing {
+    position: number;
+    pilot_id: number;
+    points: number;
+    races: number;
+    wins: number;
+    best_lap: number | null;
+    best_consecutive: number | null;
+}
+
+export interface EventStandingsDto {
+    settings: PointsSettings;
+    standings: Standing[];
+}
+
+export interface PointsSettingsDto {
+    race_event_id: number;
+    settings: PointsSettings;
+}
+
+export interface ConsecutiveRankingDto {
+    race_event_id: number;
+    laps: number;
+    class_id?: number;
+    round_id?: number;
+}
+
+export interface ConsecutiveRanking {
+    position: number;
+    pilot_id: number;
+    time: number | null;
+    race_id: string | null;
+    laps: number;
+}
+
+export type Seeding =
+    | { type: "RoundRobin" }
+    | { type: "Random"; seed: number }
+    | { type: "Ranking" };
+
+export interface HeatPlanDto {
+    race_event_id: number;
+    nodes: number;
+    channels?: string[];
+    seeding: Seeding;
+    rounds?: number;
+    format?: RaceFormat;
+    round_id?: number;
+}
+
+export type BracketType = "SingleElimination" | "DoubleElimination";
+
+export type BracketSeeding =
+    | { type: "Standings" }
+    | { type: "Consecutive"; laps: number }
+    | { type: "Manual"; pilot_ids: number[] };
+
+export interface NewBracketDto {
+    race_event_id: number;
+    name: string;
+    bracket_type: BracketType;
+    channels: string[];
+    seeding: BracketSeeding;
+    pilots?: number;
+    format?: RaceFormat;
+    round_id?: number;
+}
+
+export interface BracketRace {
+    race_id: string;
+    side: "Winners" | "Losers" | "Final";
+    round: number;
+    position: number;
+}
+
+export interface Bracket {
+    id: number;
+    name: string;
+    bracket_type: BracketType;
+    channels: string[];
+    races: BracketRace[];
+}
+
+export interface RacesUpdatedEvent {
+    race_event_id: number;
+    races: Race[];
+}
+
+export type RoundType = "Practice" | "Qualifying" | "Finals";
+
+export interface Round {
+    id: number;
+    class_id: number;
+    no: number;
+    name: string;
+    round_type: RoundType;
+}
+
+export interface Class {
+    id: number;
+    name: string;
+    pilot_ids: number[];
+    rounds: Round[];
+}
+
+export interface NewClassDto {
+    race_event_id: number;
+    name: string;
+}
+
+export interface ClassPilotsDto {
+    race_event_id: number;
+    class_id: number;
+    pilot_ids: number[];
+}
+
+export interface NewRoundDto {
+    race_event_id: number;
+    class_id: number;
+    name: string;
+    round_type: RoundType;
+}
+
+export interface RaceFilterDto {
+    race_event_id: number;
+    class_id?: number;
+    round_id?: number;
+}
+
+export type Severity = "Warning" | "Error";
+
+export type IssueKind = "SameFrequency" | "Adjacent" | "Intermodulation";
+
+export interface InterferenceIssue {
+    severity: Severity;
+    kind: IssueKind;
+    channels: string[];
+    message: string;
+}
+
+export type ErrorCode = "InvalidRequest" | "NotFound" | "Conflict" | "Device" | "DatabaseBusy" | "DatabaseVersion" | "Database" | "Internal";
+
+export interface ErrorMessage {
+    code: ErrorCode;
+    message: string;
 }
@@ -1,20 +1,41 @@
 import { Accessor, createContext, ParentProps, useContext } from "solid-js";
 import { createStore } from "solid-js/store";
-import {Heat, NewHeatDto, NewRaceDto, Pilot, Race, RaceDetailsDto, RaceEvent} from "./models";
+import {AuditEntry, AuditLogFilterDto, Bracket, Class, ClassPilotsDto, ConsecutiveRanking, ConsecutiveRankingDto, ConnectionStatus, DeviceSelection, EventStandingsDto, Heat, HeatPlanDto, InterferenceIssue, Lap, NewBracketDto, NewClassDto, NewLapDto, NewRoundDto, LapRecordedEvent, LapRefDto, LapsCorrectedEvent, LeaderboardEntry, LeaderboardUpdatedEvent, NewHeatDto, NewRaceDto, NewPilotProfileDto, Pilot, PilotProfile, PilotVideoSystemDto, PointsSettings, PointsSettingsDto, PortInfo, Race, RaceDetailsDto, RaceFilterDto, Round, RoundType, RaceEvent, RaceRefDto, RacesUpdatedEvent, RaceStatusChangedEvent, SplitLapDto, VideoSystem} from "./models";
 import { invoke } from "@tauri-apps/api/tauri";
+import { listen } from "@tauri-apps/api/event";
 
 export const initialState: State = {
   raceEvents: [],
   selectedRaceEventId: 0,
   pilots: [],
-  races: []
+  pilotProfiles: [],
+  races: [],
+  classes: [],
+  ports: [],
+  leaderboard: [],
+  standings: null,
+  consecutiveRanking: [],
+  brackets: [],
+  auditLog: [],
+  operator: "",
+  deviceStatus: { state: "Disconnected", selection: null, capabilities: null, error: null }
 }
 
 export interface State {
   raceEvents: RaceEvent[];
   selectedRaceEventId: number;
   pilots: Pilot[];
+  pilotProfiles: PilotProfile[];
   races: Race[];
+  classes: Class[];
+  ports: PortInfo[];
+  leaderboard: LeaderboardEntry[];
+  standings: EventStandingsDto | null;
+  consecutiveRanking: ConsecutiveRanking[];
+  brackets: Bracket[];
+  auditLog: AuditEntry[];
+  operator: string;
+  deviceStatus: ConnectionStatus;
 }
 
 function stateProviderFactory(initialState: State) {
@@ -44,9 +65,27 @@ function stateProviderFactory(initialState: State) {
       },
       loadRaceEventDetails(id: number) {
         invoke<RaceDetailsDto>('find_race_event_details', {raceEventId: id})
-            .then(({pilots, races}) => {
-              setState(oldState => ({...oldState, pilots, races}));
+            .then(({pilots, races, classes}) => {
+              setState(oldState => ({...oldState, pilots, races, classes}));
             })
+      },
+      loadStandings(id: number, filter: Omit<RaceFilterDto, "race_event_id"> = {}) {
+        invoke<EventStandingsDto>('find_event_standings', {raceFilterDto: {...filter, race_event_id: id} satisfies RaceFilterDto})
+            .then((standings) => {
+              setState(oldState => ({...oldState, standings}));
+            })
+      },
+      loadConsecutiveRanking(laps: number) {
+        invoke<ConsecutiveRanking[]>('find_consecutive_ranking', {consecutiveRankingDto: {race_event_id: state.selectedRaceEventId, laps} satisfies ConsecutiveRankingDto})
+            .then((consecutiveRanking) => {
+              setState(oldState => ({...oldState, consecutiveRanking}));
+            })
+            .catch(console.log);
+      },
+      setPointsSettings(settings: PointsSettings) {
+        invoke<unknown>('set_points_settings', {pointsSettingsDto: {race_event_id: state.selectedRaceEventId, settings} satisfies PointsSettingsDto})
+            .then(() => methods.raceEvents.loadStandings(state.selectedRaceEventId))
+            .catch(console.log);
       }
     },
     pilots: {
@@ -54,6 +93,41 @@ function stateProviderFactory(initialState: State) {
         invoke<Pilot>("set_pilot", { newPilotDto: { name: pilotName, race_event_id: state.selectedRaceEventId } })
           .then((pilot) => {
             setState("pilots", pilots => ([...pilots, pilot]));
+            methods.pilots.loadProfiles();
+
+  invoke<string>("get_operator").then((operator) => {
+    setState(oldState => ({...oldState, operator}));
+  });
+          }).catch(console.log);
+      },
+      loadProfiles() {
+        invoke<PilotProfile[]>("find_pilot_profiles")
+          .then((pilotProfiles) => setState(oldState => ({...oldState, pilotProfiles})));
+      },
+      addProfile(newPilotProfile: NewPilotProfileDto) {
+        invoke<PilotProfile>("add_pilot_profile", { newPilotProfileDto: newPilotProfile })
+          .then((profile) => {
+            setState("pilotProfiles", profiles => ([...profiles, profile]));
+          }).catch(console.log);
+      },
+      updateProfile(profile: PilotProfile) {
+        invoke<PilotProfile>("update_pilot_profile", { pilotProfile: profile })
+          .then((profile) => {
+            setState("pilotProfiles", oldProfile => oldProfile.id === profile.id, profile);
+            methods.raceEvents.loadRaceEventDetails(state.selectedRaceEventId);
+          }).catch(console.log);
+      },
+      importPilots() {
+        invoke<PilotProfile[]>("import_pilots", { raceEventId: state.selectedRaceEventId })
+          .then(() => {
+            methods.pilots.loadProfiles();
+            methods.raceEvents.loadRaceEventDetails(state.selectedRaceEventId);
+          }).catch(console.log);
+      },
+      setVideoSystem(pilotId: number, videoSystem: VideoSystem) {
+        invoke<Pilot>("set_pilot_video_system", { pilotVideoSystemDto: { race_event_id: state.selectedRaceEventId, pilot_id: pilotId, video_system: videoSystem } satisfies PilotVideoSystemDto })
+          .then((pilot) => {
+            setState("pilots", oldPilot => oldPilot.id === pilot.id, pilot);
           }).catch(console.log);
       },
     },
@@ -64,8 +138,131 @@ function stateProviderFactory(initialState: State) {
             setState("races", oldRaces => ([...oldRaces, newRace]))
           });
       },
-      startRace() {
-        invoke<any>('start_race', {}).then(console.log);
+      generate(plan: Omit<HeatPlanDto, "race_event_id">) {
+        invoke<Race[]>('generate_heats', { heatPlanDto: { ...plan, race_event_id: state.selectedRaceEventId } satisfies HeatPlanDto })
+          .then((newRaces: Race[]) => {
+            setState("races", oldRaces => ([...oldRaces, ...newRaces]))
+          }).catch(console.log);
+      },
+      assignChannels(raceId: string) {
+        invoke<Race>('assign_channels', { raceRefDto: { race_event_id: state.selectedRaceEventId, race_id: raceId } satisfies RaceRefDto })
+          .then((race) => {
+            setState("races", oldRace => oldRace.id === race.id, race);
+          }).catch(console.log);
+      },
+      startRace(raceId: string) {
+        methods.races.changeStatus('start_race', raceId);
+      },
+      stopRace(raceId: string) {
+        methods.races.changeStatus('stop_race', raceId);
+      },
+      interruptRace(raceId: string) {
+        methods.races.changeStatus('interrupt_race', raceId);
+      },
+      resumeRace(raceId: string) {
+        methods.races.changeStatus('resume_race', raceId);
+      },
+      finishRace(raceId: string) {
+        methods.races.changeStatus('finish_race', raceId);
+      },
+      loadLeaderboard(raceId: string) {
+        invoke<LeaderboardEntry[]>('get_leaderboard', { raceRefDto: { race_event_id: state.selectedRaceEventId, race_id: raceId } satisfies RaceRefDto })
+          .then((leaderboard) => setState(oldState => ({...oldState, leaderboard})))
+          .catch(console.log);
+      },
+      changeStatus(command: string, raceId: string) {
+        invoke<Race>(command, { raceRefDto: { race_event_id: state.selectedRaceEventId, race_id: raceId } satisfies RaceRefDto })
+          .then((updatedRace: Race) => {
+            setState("races", oldRaces => oldRaces.map(race => race.id === updatedRace.id ? updatedRace : race))
+          }).catch(console.log);
+      }
+    },
+    classes: {
+      addOne(name: string) {
+        invoke<Class>('add_class', {newClassDto: {race_event_id: state.selectedRaceEventId, name} satisfies NewClassDto})
+          .then((newClass) => setState("classes", classes => ([...classes, newClass])))
+          .catch(console.log);
+      },
+      setPilots(classId: number, pilotIds: number[]) {
+        invoke<Class>('set_class_pilots', {classPilotsDto: {race_event_id: state.selectedRaceEventId, class_id: classId, pilot_ids: pilotIds} satisfies ClassPilotsDto})
+          .then((updatedClass) => setState("classes", classes => classes.map(oldClass => oldClass.id === updatedClass.id ? updatedClass : oldClass)))
+          .catch(console.log);
+      },
+      addRound(classId: number, name: string, roundType: RoundType) {
+        invoke<Round>('add_round', {newRoundDto: {race_event_id: state.selectedRaceEventId, class_id: classId, name, round_type: roundType} satisfies NewRoundDto})
+          .then((round) => setState("classes", oldClass => oldClass.id === classId, "rounds", rounds => ([...rounds, round])))
+          .catch(console.log);
+      },
+      filterRaces(filter: Omit<RaceFilterDto, "race_event_id">) {
+        return invoke<Race[]>('find_races', {raceFilterDto: {...filter, race_event_id: state.selectedRaceEventId} satisfies RaceFilterDto});
+      }
+    },
+    brackets: {
+      load() {
+        invoke<Bracket[]>('find_brackets', {raceEventId: state.selectedRaceEventId})
+          .then((brackets) => setState(oldState => ({...oldState, brackets})));
+      },
+      addOne(newBracket: Omit<NewBracketDto, NewClassDto, NewLapDto, NewRoundDto, "race_event_id">) {
+        invoke<Bracket>('create_bracket', {newBracketDto: {...newBracket, race_event_id: state.selectedRaceEventId} satisfies NewBracketDto})
+          .then((bracket) => {
+            setState("brackets", brackets => ([...brackets, bracket]));
+            methods.raceEvents.loadRaceEventDetails(state.selectedRaceEventId);
+          }).catch(console.log);
+      }
+    },
+    audit: {
+      load(raceId?: number) {
+        invoke<AuditEntry[]>('find_audit_log', { auditLogFilterDto: { race_event_id: state.selectedRaceEventId, race_id: raceId } satisfies AuditLogFilterDto })
+          .then((auditLog) => setState(oldState => ({...oldState, auditLog})))
+          .catch(console.log);
+      },
+      setOperator(operator: string) {
+        invoke<string>('set_operator', { operator })
+          .then((operator) => setState(oldState => ({...oldState, operator})))
+          .catch(console.log);
+      }
+    },
+    laps: {
+      add(raceId: string, heatId: number, crossedAt?: number) {
+        invoke<Lap[]>('add_lap', { newLapDto: { race_event_id: state.selectedRaceEventId, race_id: raceId, heat_id: heatId, crossed_at: crossedAt } satisfies NewLapDto })
+          .catch(console.log);
+      },
+      remove(raceId: string, lapId: number) {
+        methods.laps.correct('delete_lap', raceId, lapId);
+      },
+      restore(raceId: string, lapId: number) {
+        methods.laps.correct('restore_lap', raceId, lapId);
+      },
+      merge(raceId: string, lapId: number) {
+        methods.laps.correct('merge_laps', raceId, lapId);
+      },
+      split(raceId: string, lapId: number, crossedAt?: number) {
+        invoke<Lap[]>('split_lap', { splitLapDto: { race_event_id: state.selectedRaceEventId, race_id: raceId, lap_id: lapId, crossed_at: crossedAt } satisfies SplitLapDto })
+          .catch(console.log);
+      },
+      correct(command: string, raceId: string, lapId: number) {
+        invoke<Lap[]>(command, { lapRefDto: { race_event_id: state.selectedRaceEventId, race_id: raceId, lap_id: lapId } satisfies LapRefDto })
+          .catch(console.log);
+      }
+    },
+    channels: {
+      check(channels: string[]) {
+        return invoke<InterferenceIssue[]>('check_channels', {channels});
+      }
+    },
+    device: {
+      loadPorts() {
+        invoke<PortInfo[]>('list_devices')
+          .then((ports) => setState(oldState => ({...oldState, ports})));
+      },
+      connect(deviceSelection: DeviceSelection) {
+        invoke<ConnectionStatus>('connect_device', { deviceSelection })
+          .then((deviceStatus) => setState(oldState => ({...oldState, deviceStatus})))
+          .catch(console.log);
+      },
+      disconnect() {
+        invoke<ConnectionStatus>('disconnect_device')
+          .then((deviceStatus) => setState(oldState => ({...oldState, deviceStatus})));
       }
     }
   }
@@ -74,6 +271,51 @@ function stateProviderFactory(initialState: State) {
     setState("raceEvents", initState.race_events);
   });
 
+  methods.pilots.loadProfiles();
+
+  invoke<ConnectionStatus>("get_device_status").then((deviceStatus) => {
+    setState(oldState => ({...oldState, deviceStatus}));
+  });
+
+  listen<LapRecordedEvent>("lap-recorded", ({payload}) => {
+    if (payload.race_event_id !== state.selectedRaceEventId) {
+      return;
+    }
+    setState("races", race => race.id === payload.race_id, "heats", heat => heat.id === payload.lap.heat_id, "laps", payload.laps);
+  });
+
+  listen<LapsCorrectedEvent>("laps-corrected", ({payload}) => {
+    if (payload.race_event_id !== state.selectedRaceEventId) {
+      return;
+    }
+    setState("races", race => race.id === payload.race_id, "heats", heat => heat.id === payload.heat_id, "laps", payload.laps);
+  });
+
+  listen<LeaderboardUpdatedEvent>("leaderboard-updated", ({payload}) => {
+    if (payload.race_event_id !== state.selectedRaceEventId) {
+      return;
+    }
+    setState(oldState => ({...oldState, leaderboard: payload.entries}));
+  });
+
+  listen<RaceStatusChangedEvent>("race-status-changed", ({payload}) => {
+    if (payload.race_event_id !== state.selectedRaceEventId) {
+      return;
+    }
+    setState("races", oldRaces => oldRaces.map(race => race.id === payload.race.id ? payload.race : race));
+  });
+
+  listen<RacesUpdatedEvent>("races-updated", ({payload}) => {
+    if (payload.race_event_id !== state.selectedRaceEventId) {
+      return;
+    }
+    setState("races", oldRaces => oldRaces.map(race => payload.races.find(updatedRace => updatedRace.id === race.id) ?? race));
+  });
+
+  listen<ConnectionStatus>("device-status", ({payload}) => {
+    setState(oldState => ({...oldState, deviceStatus: payload}));
+  });
+
   return [
     state,
     methods